@@ -1,4 +1,6 @@
-use crate::note::Note;
+use crate::config::Config;
+use crate::note::{Note, MIN_WIDTH};
+use std::time::{Duration, Instant};
 
 pub enum CurrentScreen {
     Main,
@@ -14,35 +16,115 @@ pub struct App {
     pub note_focus: Option<usize>,
     pub clipboard: String,
     pub modified: bool,
+    pub read_only: bool,
+    pub collapsed: bool,
+    pub hide_completed: bool,
+    pub jump: Option<usize>,   // Board position typed so far in jump mode
+    pub toast: Option<String>, // Shown in the footer until the next key press
+    pub last_input: Instant,
+    pub config: Config,
 }
 
 impl App {
-    pub fn new(items: Vec<Note>) -> App {
-        let app = App {
+    pub fn new(items: Vec<Note>, config: Config) -> App {
+        let mut app = App {
             current_screen: CurrentScreen::Main,
             notes: items,
             note_focus: None,
             clipboard: String::new(),
             modified: false,
+            read_only: false,
+            collapsed: false,
+            hide_completed: config.layout.hide_completed,
+            jump: None,
+            toast: None,
+            last_input: Instant::now(),
+            config,
         };
-
+        app.pin_inbox();
         app
     }
-    pub fn add_note(&mut self, title: String) {
+
+    // Moves the inbox note to the front of the board, creating it if it is missing
+    fn pin_inbox(&mut self) {
+        if self.config.inbox_note.is_empty() {
+            return;
+        }
+        let inbox = match self
+            .notes
+            .iter()
+            .position(|note| note.title == self.config.inbox_note)
+        {
+            Some(index) => self.notes.remove(index),
+            None => Note::new(self.config.inbox_note.clone()),
+        };
+        self.notes.insert(0, inbox);
+    }
+
+    // Only the pinned note counts, so other notes that share its title can still be deleted
+    pub fn is_inbox(&self, index: usize) -> bool {
+        index == 0
+            && !self.config.inbox_note.is_empty()
+            && self
+                .notes
+                .first()
+                .is_some_and(|note| note.title == self.config.inbox_note)
+    }
+    pub fn add_note(&mut self, mut title: String) -> Result<(), String> {
+        let max_len = self.config.max_title_len;
+        if title.chars().count() > max_len {
+            if self.config.reject_long_titles {
+                return Err(format!("Title is longer than {} characters", max_len));
+            }
+            title = title.chars().take(max_len).collect();
+        }
         self.modified = true;
         self.notes.push(Note::new(title));
+        if self.config.focus_new {
+            self.focus_note(self.notes.len() - 1);
+        }
+        Ok(())
+    }
+
+    // Returns true when the app can quit right away, otherwise asks whether to save
+    pub fn request_quit(&mut self) -> bool {
+        if !self.modified && !self.config.confirm_quit {
+            return true;
+        }
+        self.current_screen = CurrentScreen::Exiting;
+        false
+    }
+
+    // Time left before the idle footer hides, or None if it never hides
+    pub fn footer_hides_in(&self) -> Option<Duration> {
+        match self.config.footer_autohide_secs {
+            0 => None,
+            secs => Some(Duration::from_secs(secs).saturating_sub(self.last_input.elapsed())),
+        }
+    }
+
+    pub fn footer_hidden(&self) -> bool {
+        matches!(self.current_screen, CurrentScreen::Main)
+            && self.toast.is_none()
+            && self.footer_hides_in() == Some(Duration::ZERO)
     }
 
     pub fn move_focus_right(&mut self) {
         if let Some(note_focus) = self.note_focus {
             self.notes.get_mut(note_focus).unwrap().unfocus();
             self.note_focus = Some((note_focus + 1) % self.notes.len());
+            if self.config.wrap_indicator
+                && self.notes.len() > 1
+                && note_focus + 1 == self.notes.len()
+            {
+                self.toast = Some("Wrapped to the first note".to_string());
+            }
             self.notes
                 .get_mut(self.note_focus.unwrap())
                 .unwrap()
                 .focus();
         } else {
-            if let Some(_) = self.notes.first() {
+            if !self.notes.is_empty() {
                 self.note_focus = Some(0);
                 self.notes
                     .get_mut(self.note_focus.unwrap())
@@ -58,6 +140,9 @@ impl App {
             self.note_focus = if note_focus != 0 {
                 Some(note_focus - 1)
             } else {
+                if self.config.wrap_indicator && self.notes.len() > 1 {
+                    self.toast = Some("Wrapped to the last note".to_string());
+                }
                 Some(self.notes.len() - 1)
             };
             self.notes
@@ -65,7 +150,7 @@ impl App {
                 .unwrap()
                 .focus();
         } else {
-            if let Some(_) = self.notes.first() {
+            if !self.notes.is_empty() {
                 self.note_focus = Some(self.notes.len() - 1);
                 self.notes
                     .get_mut(self.note_focus.unwrap())
@@ -75,15 +160,68 @@ impl App {
         }
     }
 
-    pub fn get_focused_note(&self) -> Option<usize> {
-        if let Some(note_index) = self.note_focus {
-            Some(note_index)
-        } else {
-            None
+    pub fn focus_note(&mut self, index: usize) {
+        if let Some(note_focus) = self.note_focus {
+            if let Some(note) = self.notes.get_mut(note_focus) {
+                note.unfocus();
+            }
         }
+        if let Some(note) = self.notes.get_mut(index) {
+            note.focus();
+            self.note_focus = Some(index);
+        }
+    }
+
+    // Moves a note to a new position on the board, keeping focus on the same note.
+    // The inbox stays in front of everything else
+    pub fn move_to(&mut self, from: usize, to: usize) {
+        let first = if self.is_inbox(0) { 1 } else { 0 };
+        let to = to.max(first);
+        if from >= self.notes.len() || to >= self.notes.len() || from == to || from < first {
+            return;
+        }
+        let note = self.notes.remove(from);
+        self.notes.insert(to, note);
+        if let Some(focus) = &mut self.note_focus {
+            *focus = if *focus == from {
+                to
+            } else if from < *focus && *focus <= to {
+                *focus - 1
+            } else if to <= *focus && *focus < from {
+                *focus + 1
+            } else {
+                *focus
+            };
+        }
+        self.modified = true;
+    }
+
+    pub fn resize_focused_note(&mut self, step: i16) {
+        if let Some(note) = self.note_focus.and_then(|i| self.notes.get_mut(i)) {
+            note.width = note.width.saturating_add_signed(step).max(MIN_WIDTH);
+        }
+    }
+
+    // Finds the next note after the focused one that still has incomplete items
+    pub fn next_note_with_work(&self) -> Option<usize> {
+        let start = self.note_focus.map_or(0, |focus| focus + 1);
+        (0..self.notes.len())
+            .map(|offset| (start + offset) % self.notes.len())
+            .find(|&i| self.notes[i].total_count() > 0 && !self.notes[i].is_complete())
+    }
+
+    pub fn get_focused_note(&self) -> Option<usize> {
+        self.note_focus
     }
 
     pub fn delete_note(&mut self, index: usize) {
+        if self.is_inbox(index) {
+            self.toast = Some(format!(
+                "{} is the inbox and can't be deleted",
+                self.notes[index].title
+            ));
+            return;
+        }
         if let Some(note_index) = &mut self.note_focus {
             if *note_index != 0 {
                 *note_index = if *note_index >= index {
@@ -97,3 +235,164 @@ impl App {
         self.modified = true;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn board_with(titles: &[&str], config: Config) -> App {
+        let notes = titles.iter().map(|title| Note::new(title.to_string()));
+        App::new(notes.collect(), config)
+    }
+
+    fn inbox_config() -> Config {
+        Config {
+            inbox_note: "Inbox".to_string(),
+            ..Config::default()
+        }
+    }
+
+    #[test]
+    fn adding_a_note_marks_the_app_modified() {
+        let mut app = board_with(&[], Config::default());
+        app.add_note("New".to_string()).unwrap();
+        assert!(app.modified);
+    }
+
+    #[test]
+    fn deleting_a_note_marks_the_app_modified() {
+        let mut app = board_with(&["A", "B"], Config::default());
+        app.delete_note(0);
+        assert!(app.modified);
+        assert_eq!(app.notes.len(), 1);
+    }
+
+    #[test]
+    fn moving_a_note_marks_the_app_modified() {
+        let mut app = board_with(&["A", "B", "C"], Config::default());
+        app.move_to(2, 0);
+        assert!(app.modified);
+        let titles: Vec<&str> = app.notes.iter().map(|note| note.title.as_str()).collect();
+        assert_eq!(titles, ["C", "A", "B"]);
+    }
+
+    #[test]
+    fn view_only_actions_leave_the_app_unmodified() {
+        let mut app = board_with(&["A", "B", "C"], Config::default());
+        app.move_focus_right();
+        app.move_focus_left();
+        app.focus_note(2);
+        app.resize_focused_note(3);
+        app.resize_focused_note(-10);
+        app.move_to(1, 1);
+        app.move_to(0, 5);
+        assert!(!app.modified);
+    }
+
+    #[test]
+    fn wrapping_is_only_announced_with_several_notes() {
+        let wrap_indicator = || Config {
+            wrap_indicator: true,
+            ..Config::default()
+        };
+        let mut app = board_with(&["A"], wrap_indicator());
+        for _ in 0..3 {
+            app.move_focus_right();
+            app.move_focus_left();
+        }
+        assert!(app.toast.is_none());
+
+        let mut app = board_with(&["A", "B"], wrap_indicator());
+        app.move_focus_right();
+        app.move_focus_right();
+        app.move_focus_left();
+        assert!(app.toast.is_none());
+        app.move_focus_left();
+        assert_eq!(app.toast.as_deref(), Some("Wrapped to the last note"));
+        app.move_focus_right();
+        assert_eq!(app.toast.as_deref(), Some("Wrapped to the first note"));
+    }
+
+    #[test]
+    fn deleting_the_inbox_leaves_the_app_unmodified() {
+        let mut app = board_with(&["Inbox", "A"], inbox_config());
+        app.delete_note(0);
+        assert!(!app.modified);
+        assert_eq!(app.notes.len(), 2);
+        assert!(app.toast.is_some());
+    }
+
+    #[test]
+    fn inbox_is_pinned_first_or_created() {
+        let app = board_with(&["A", "Inbox", "B"], inbox_config());
+        assert_eq!(app.notes[0].title, "Inbox");
+        assert_eq!(app.notes.len(), 3);
+
+        let app = board_with(&["A"], inbox_config());
+        assert_eq!(app.notes[0].title, "Inbox");
+        assert_eq!(app.notes.len(), 2);
+    }
+
+    #[test]
+    fn notes_sharing_the_inbox_title_can_be_deleted() {
+        let mut app = board_with(&["Inbox", "A"], inbox_config());
+        app.add_note("Inbox".to_string()).unwrap();
+        assert!(app.is_inbox(0));
+        assert!(!app.is_inbox(2));
+        app.delete_note(2);
+        assert_eq!(app.notes.len(), 2);
+        assert!(app.toast.is_none());
+    }
+
+    #[test]
+    fn nothing_moves_in_front_of_the_inbox() {
+        let mut app = board_with(&["Inbox", "A", "B"], inbox_config());
+        app.move_to(2, 0);
+        app.move_to(0, 2);
+        let titles: Vec<&str> = app.notes.iter().map(|note| note.title.as_str()).collect();
+        assert_eq!(titles, ["Inbox", "B", "A"]);
+    }
+
+    #[test]
+    fn long_titles_are_truncated_by_default() {
+        let mut app = board_with(
+            &[],
+            Config {
+                max_title_len: 3,
+                ..Config::default()
+            },
+        );
+        assert_eq!(app.add_note("abcdef".to_string()), Ok(()));
+        assert_eq!(app.notes[0].title, "abc");
+    }
+
+    #[test]
+    fn long_titles_are_rejected_when_configured() {
+        let mut app = board_with(
+            &[],
+            Config {
+                max_title_len: 3,
+                reject_long_titles: true,
+                ..Config::default()
+            },
+        );
+        assert!(app.add_note("abcdef".to_string()).is_err());
+        assert!(app.notes.is_empty());
+        assert!(!app.modified);
+        assert_eq!(app.add_note("abc".to_string()), Ok(()));
+        assert_eq!(app.notes[0].title, "abc");
+    }
+
+    #[test]
+    fn title_length_counts_characters() {
+        let mut app = board_with(
+            &[],
+            Config {
+                max_title_len: 3,
+                ..Config::default()
+            },
+        );
+        app.add_note("ééééé".to_string()).unwrap();
+        assert_eq!(app.notes[0].title, "ééé");
+    }
+}
@@ -0,0 +1,402 @@
+use crate::note::ItemStatus;
+use std::fs;
+use std::io;
+use std::path::Path;
+use unicode_width::UnicodeWidthStr;
+
+#[derive(Default, Clone, Copy)]
+pub struct EditConfig {
+    pub word_count: bool,
+    pub esc_timeout_ms: u64,
+    pub max_items: usize,
+    pub hierarchical: bool,
+}
+
+pub struct LayoutConfig {
+    pub highlight_duplicates: bool,
+    pub duplicates_ignore_case: bool,
+    pub collapse_completed: bool,
+    pub progress_bar: bool,
+    pub align_items: bool,
+    pub note_gap: bool,
+    pub tree_items: bool,
+    pub hide_completed: bool,
+    pub compact_footer_width: u16,
+    pub todo_str: String,
+    pub doing_str: String,
+    pub complete_str: String,
+}
+
+impl Default for LayoutConfig {
+    fn default() -> Self {
+        LayoutConfig {
+            highlight_duplicates: false,
+            duplicates_ignore_case: false,
+            collapse_completed: false,
+            progress_bar: false,
+            align_items: false,
+            note_gap: false,
+            tree_items: false,
+            hide_completed: false,
+            compact_footer_width: 80,
+            todo_str: ItemStatus::Todo.marker().to_string(),
+            doing_str: ItemStatus::Doing.marker().to_string(),
+            complete_str: ItemStatus::Done.marker().to_string(),
+        }
+    }
+}
+
+impl LayoutConfig {
+    pub fn marker(&self, status: ItemStatus) -> &str {
+        match status {
+            ItemStatus::Todo => &self.todo_str,
+            ItemStatus::Doing => &self.doing_str,
+            ItemStatus::Done => &self.complete_str,
+        }
+    }
+
+    pub fn marker_width(&self) -> usize {
+        [&self.todo_str, &self.doing_str, &self.complete_str]
+            .iter()
+            .map(|marker| marker.width())
+            .max()
+            .unwrap_or(0)
+    }
+}
+
+pub struct Config {
+    pub focus_new: bool,
+    pub confirm_quit: bool,
+    pub wrap_indicator: bool,
+    pub footer_autohide_secs: u64,
+    pub advance_on_complete: bool,
+    pub max_title_len: usize,
+    pub reject_long_titles: bool,
+    pub show_ids: bool, // Debugging aid, not documented
+    pub open_command: String,
+    pub inbox_note: String,
+    pub edit: EditConfig,
+    pub layout: LayoutConfig,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            focus_new: true,
+            confirm_quit: false,
+            wrap_indicator: false,
+            footer_autohide_secs: 0,
+            advance_on_complete: false,
+            max_title_len: 256,
+            reject_long_titles: false,
+            show_ids: false,
+            open_command: if cfg!(target_os = "macos") {
+                "open".to_string()
+            } else {
+                "xdg-open".to_string()
+            },
+            inbox_note: String::new(),
+            edit: EditConfig::default(),
+            layout: LayoutConfig::default(),
+        }
+    }
+}
+
+impl Config {
+    // A missing file gives the defaults, but one that can't be read is an error. Lines that can't
+    // be applied are skipped and reported as warnings
+    pub fn load(path: &Path) -> io::Result<(Config, Vec<String>)> {
+        match fs::read_to_string(path) {
+            Ok(text) => {
+                let (config, warnings) = Config::parse(&text);
+                let warnings = warnings
+                    .into_iter()
+                    .map(|warning| format!("{}:{}", path.display(), warning))
+                    .collect();
+                Ok((config, warnings))
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                Ok((Config::default(), Vec::new()))
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    pub fn parse(text: &str) -> (Config, Vec<String>) {
+        let mut config = Config::default();
+        let mut warnings = Vec::new();
+        let mut section = String::new();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let applied =
+                if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+                    section = name.trim().to_string();
+                    Ok(())
+                } else if let Some((key, value)) = line.split_once('=') {
+                    parse_value(value).and_then(|value| config.set(&section, key.trim(), &value))
+                } else {
+                    Err("expected `key = value`".to_string())
+                };
+            if let Err(warning) = applied {
+                warnings.push(format!("{}: {}", number + 1, warning));
+            }
+        }
+        (config, warnings)
+    }
+
+    pub fn to_toml(&self) -> String {
+        format!(
+            "focus_new = {}
+confirm_quit = {}
+wrap_indicator = {}
+footer_autohide_secs = {}
+advance_on_complete = {}
+max_title_len = {}
+reject_long_titles = {}
+show_ids = {}
+open_command = {}
+inbox_note = {}
+
+[edit]
+word_count = {}
+esc_timeout_ms = {}
+max_items = {}
+hierarchical = {}
+
+[layout]
+highlight_duplicates = {}
+duplicates_ignore_case = {}
+collapse_completed = {}
+progress_bar = {}
+align_items = {}
+note_gap = {}
+tree_items = {}
+hide_completed = {}
+compact_footer_width = {}
+todo_str = {}
+doing_str = {}
+complete_str = {}
+",
+            self.focus_new,
+            self.confirm_quit,
+            self.wrap_indicator,
+            self.footer_autohide_secs,
+            self.advance_on_complete,
+            self.max_title_len,
+            self.reject_long_titles,
+            self.show_ids,
+            quote(&self.open_command),
+            quote(&self.inbox_note),
+            self.edit.word_count,
+            self.edit.esc_timeout_ms,
+            self.edit.max_items,
+            self.edit.hierarchical,
+            self.layout.highlight_duplicates,
+            self.layout.duplicates_ignore_case,
+            self.layout.collapse_completed,
+            self.layout.progress_bar,
+            self.layout.align_items,
+            self.layout.note_gap,
+            self.layout.tree_items,
+            self.layout.hide_completed,
+            self.layout.compact_footer_width,
+            quote(&self.layout.todo_str),
+            quote(&self.layout.doing_str),
+            quote(&self.layout.complete_str),
+        )
+    }
+
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, self.to_toml())
+    }
+
+    // Like write, but refuses to replace an existing file unless forced
+    pub fn save(&self, path: &Path, force: bool) -> io::Result<()> {
+        if !force && path.exists() {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("{} already exists", path.display()),
+            ));
+        }
+        self.write(path)
+    }
+
+    fn set(&mut self, section: &str, key: &str, value: &str) -> Result<(), String> {
+        let name = if section.is_empty() {
+            key.to_string()
+        } else {
+            format!("{}.{}", section, key)
+        };
+        let parsed = match (section, key) {
+            ("", "focus_new") => set_parsed(&mut self.focus_new, value),
+            ("", "confirm_quit") => set_parsed(&mut self.confirm_quit, value),
+            ("", "wrap_indicator") => set_parsed(&mut self.wrap_indicator, value),
+            ("", "footer_autohide_secs") => set_parsed(&mut self.footer_autohide_secs, value),
+            ("", "advance_on_complete") => set_parsed(&mut self.advance_on_complete, value),
+            ("", "max_title_len") => set_parsed(&mut self.max_title_len, value),
+            ("", "reject_long_titles") => set_parsed(&mut self.reject_long_titles, value),
+            ("", "show_ids") => set_parsed(&mut self.show_ids, value),
+            ("", "open_command") => set_parsed(&mut self.open_command, value),
+            ("", "inbox_note") => set_parsed(&mut self.inbox_note, value),
+            ("edit", "word_count") => set_parsed(&mut self.edit.word_count, value),
+            ("edit", "esc_timeout_ms") => set_parsed(&mut self.edit.esc_timeout_ms, value),
+            ("edit", "max_items") => set_parsed(&mut self.edit.max_items, value),
+            ("edit", "hierarchical") => set_parsed(&mut self.edit.hierarchical, value),
+            ("layout", "highlight_duplicates") => {
+                set_parsed(&mut self.layout.highlight_duplicates, value)
+            }
+            ("layout", "duplicates_ignore_case") => {
+                set_parsed(&mut self.layout.duplicates_ignore_case, value)
+            }
+            ("layout", "collapse_completed") => {
+                set_parsed(&mut self.layout.collapse_completed, value)
+            }
+            ("layout", "progress_bar") => set_parsed(&mut self.layout.progress_bar, value),
+            ("layout", "align_items") => set_parsed(&mut self.layout.align_items, value),
+            ("layout", "note_gap") => set_parsed(&mut self.layout.note_gap, value),
+            ("layout", "tree_items") => set_parsed(&mut self.layout.tree_items, value),
+            ("layout", "hide_completed") => set_parsed(&mut self.layout.hide_completed, value),
+            ("layout", "compact_footer_width") => {
+                set_parsed(&mut self.layout.compact_footer_width, value)
+            }
+            ("layout", "todo_str") => set_parsed(&mut self.layout.todo_str, value),
+            ("layout", "doing_str") => set_parsed(&mut self.layout.doing_str, value),
+            ("layout", "complete_str") => set_parsed(&mut self.layout.complete_str, value),
+            _ => return Err(format!("unknown setting `{}`", name)),
+        };
+        if parsed {
+            Ok(())
+        } else {
+            Err(format!("invalid value `{}` for `{}`", value, name))
+        }
+    }
+}
+
+// Quotes a string value, escaping it the way parse_value expects
+fn quote(value: &str) -> String {
+    let mut quoted = String::from('"');
+    for c in value.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\t' => quoted.push_str("\\t"),
+            '\r' => quoted.push_str("\\r"),
+            c if c.is_control() => quoted.push_str(&format!("\\u{:04X}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+// Unquotes and unescapes string values and strips trailing comments from everything else
+fn parse_value(value: &str) -> Result<String, String> {
+    let value = value.trim();
+    let Some(quoted) = value.strip_prefix('"') else {
+        return Ok(value
+            .split('#')
+            .next()
+            .unwrap_or_default()
+            .trim()
+            .to_string());
+    };
+    let mut parsed = String::new();
+    let mut chars = quoted.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => return Ok(parsed),
+            '\\' => parsed.push(match chars.next() {
+                Some('"') => '"',
+                Some('\\') => '\\',
+                Some('n') => '\n',
+                Some('t') => '\t',
+                Some('r') => '\r',
+                Some('u') => {
+                    let hex: String = chars.by_ref().take(4).collect();
+                    u32::from_str_radix(&hex, 16)
+                        .ok()
+                        .and_then(char::from_u32)
+                        .ok_or_else(|| format!("invalid escape `\\u{}`", hex))?
+                }
+                other => {
+                    let other = other.map(String::from).unwrap_or_default();
+                    return Err(format!("invalid escape `\\{}`", other));
+                }
+            }),
+            c => parsed.push(c),
+        }
+    }
+    Err("unterminated string".to_string())
+}
+
+// Returns false if the value doesn't parse, leaving the field as it was
+fn set_parsed<T: std::str::FromStr>(field: &mut T, value: &str) -> bool {
+    match value.parse() {
+        Ok(value) => {
+            *field = value;
+            true
+        }
+        Err(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn written_config_loads_back_unchanged() {
+        let mut config = Config {
+            open_command: "say \"hi\" \\ tab\there".to_string(),
+            max_title_len: 12,
+            inbox_note: "GTD #1".to_string(),
+            ..Config::default()
+        };
+        config.layout.todo_str = "a\"b".to_string();
+        config.layout.doing_str = "\\".to_string();
+        config.layout.complete_str = "\u{1b}[x]\n".to_string();
+
+        let (loaded, warnings) = Config::parse(&config.to_toml());
+        assert!(warnings.is_empty(), "{:?}", warnings);
+        assert_eq!(loaded.open_command, config.open_command);
+        assert_eq!(loaded.max_title_len, 12);
+        assert_eq!(loaded.inbox_note, "GTD #1");
+        assert_eq!(loaded.layout.todo_str, "a\"b");
+        assert_eq!(loaded.layout.doing_str, "\\");
+        assert_eq!(loaded.layout.complete_str, "\u{1b}[x]\n");
+        assert_eq!(loaded.to_toml(), config.to_toml());
+    }
+
+    #[test]
+    fn comments_after_values_are_ignored() {
+        let (config, warnings) =
+            Config::parse("max_title_len = 10 # short\nopen_command = \"xdg-open\" # \"quoted\"\n");
+        assert!(warnings.is_empty(), "{:?}", warnings);
+        assert_eq!(config.max_title_len, 10);
+        assert_eq!(config.open_command, "xdg-open");
+    }
+
+    #[test]
+    fn unknown_keys_and_bad_values_are_reported() {
+        let (config, warnings) = Config::parse(
+            "focus_nwe = false\n[edit]\nmax_items = lots\nword_count = true\nnot a setting\n",
+        );
+        assert!(config.edit.word_count);
+        assert_eq!(config.edit.max_items, 0);
+        assert_eq!(
+            warnings,
+            [
+                "1: unknown setting `focus_nwe`",
+                "3: invalid value `lots` for `edit.max_items`",
+                "5: expected `key = value`",
+            ]
+        );
+    }
+}
@@ -1,35 +1,127 @@
 use crate::ui::ui;
 use app::{App, CurrentScreen};
+use config::Config;
 use crossterm::{
-    event::{self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode},
+    event::{
+        self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyModifiers,
+        KeyboardEnhancementFlags, PopKeyboardEnhancementFlags, PushKeyboardEnhancementFlags,
+    },
     execute,
-    terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
+    terminal::{
+        disable_raw_mode, enable_raw_mode, supports_keyboard_enhancement, EnterAlternateScreen,
+        LeaveAlternateScreen,
+    },
 };
+use note::Note;
 use ratatui::{
     backend::{Backend, CrosstermBackend},
     Terminal,
 };
-use std::{self, io};
+use std::{
+    self,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    time::{Duration, Instant},
+};
 use ui::send_err;
 use ui::send_message;
 
 mod app;
+mod config;
 mod note;
 mod ui;
 mod utils;
 mod vim;
 
+const REDRAW_INTERVAL: Duration = Duration::from_secs(60);
+const HELP: &str = "Ctrl-c or q in the main view - quit, w - write changes, wq - write changes and quit, q! - dicard changes and quit, q - quit, new <title> - add a note, saveconfig[!] <path> - save settings, open-data - open the data directory, help - display this message";
+
+#[derive(Default)]
+struct Args {
+    config: Option<PathBuf>,
+    init_config: bool,
+    save_config: Option<PathBuf>,
+}
+
+fn parse_args() -> Result<Args, String> {
+    let mut args = Args::default();
+    let mut argv = std::env::args().skip(1);
+    while let Some(arg) = argv.next() {
+        match arg.as_str() {
+            "--config" => match argv.next() {
+                Some(path) => args.config = Some(PathBuf::from(path)),
+                None => return Err("--config requires a path".to_string()),
+            },
+            "--init-config" => args.init_config = true,
+            "--save-config" => match argv.next() {
+                Some(path) => args.save_config = Some(PathBuf::from(path)),
+                None => return Err("--save-config requires a path".to_string()),
+            },
+            _ => return Err(format!("unknown argument {}", arg)),
+        }
+    }
+    Ok(args)
+}
+
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    if let Some(notes) = utils::get_notes_from_file() {
+    let args = parse_args().unwrap_or_else(|err| {
+        eprintln!("keep: {}", err);
+        std::process::exit(1);
+    });
+    let config_path = args
+        .config
+        .unwrap_or_else(|| utils::config_file("config.toml"));
+    let (config, warnings) = Config::load(&config_path).unwrap_or_else(|err| {
+        eprintln!("keep: could not read {}: {}", config_path.display(), err);
+        std::process::exit(1);
+    });
+    for warning in &warnings {
+        eprintln!("keep: {}", warning);
+    }
+    if args.init_config && !config_path.exists() {
+        Config::default().write(&config_path)?;
+        println!("Wrote default config to {}", config_path.display());
+    }
+    if let Some(path) = args.save_config {
+        if let Err(err) = config.save(&path, false) {
+            eprintln!("keep: could not save config: {}", err);
+            std::process::exit(1);
+        }
+        println!("Wrote config to {}", path.display());
+        return Ok(());
+    }
+
+    let notes = utils::get_notes_from_file().unwrap_or_else(|err| {
+        eprintln!(
+            "keep: could not read {}: {}",
+            utils::notes_path().display(),
+            err
+        );
+        std::process::exit(1);
+    });
+    if let Some(notes) = notes {
         enable_raw_mode()?;
         let mut stdout = io::stdout();
         execute!(stdout, EnterAlternateScreen, EnableMouseCapture)?;
+        // Terminals without the kitty keyboard protocol may misbehave if the flags are pushed
+        let keyboard_enhanced = matches!(supports_keyboard_enhancement(), Ok(true));
+        if keyboard_enhanced {
+            execute!(
+                stdout,
+                PushKeyboardEnhancementFlags(KeyboardEnhancementFlags::DISAMBIGUATE_ESCAPE_CODES)
+            )?;
+        }
         let backend = CrosstermBackend::new(stdout);
         let mut terminal = Terminal::new(backend)?;
-        let mut app = App::new(notes);
+        let mut app = App::new(notes, config);
+        if let Some(warning) = warnings.first() {
+            app.toast = Some(format!("Config: {} (all warnings are on stderr)", warning));
+        }
+        app.read_only = !utils::is_writable(&utils::notes_path());
         let res = run_app(&mut terminal, &mut app);
-        if let Ok(true) = res {
-            utils::write_notes_to_file(&app.notes)?;
+        if keyboard_enhanced {
+            execute!(terminal.backend_mut(), PopKeyboardEnhancementFlags)?;
         }
         disable_raw_mode()?;
         execute!(
@@ -38,20 +130,54 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             DisableMouseCapture
         )?;
         terminal.show_cursor()?;
+        // Save only after the terminal is restored so errors and the retry prompt are readable
+        if res? {
+            save_notes(&app.notes)?;
+        }
     } else {
-        println!("To save notes across sessions, create the following file $HOME/.config/keep/keep_config.txt");
+        println!("To save notes across sessions, create the following file $XDG_CONFIG_HOME/keep/keep_config.txt or $HOME/.config/keep/keep_config.txt");
     }
 
     Ok(())
 }
 
+// Writes the notes, asking for another path while writing fails
+fn save_notes(notes: &[Note]) -> io::Result<()> {
+    let mut path = utils::notes_path();
+    loop {
+        match utils::write_notes_to_file(notes, &path) {
+            Ok(()) => return Ok(()),
+            Err(err) => {
+                eprintln!("Could not save notes to {}: {}", path.display(), err);
+                print!("Enter another path to save to (leave empty to discard changes): ");
+                io::stdout().flush()?;
+                let mut line = String::new();
+                io::stdin().read_line(&mut line)?;
+                if line.trim().is_empty() {
+                    return Err(err);
+                }
+                path = PathBuf::from(line.trim());
+            }
+        }
+    }
+}
+
 fn run_app<B: Backend>(terminal: &mut Terminal<B>, app: &mut App) -> io::Result<bool> {
     loop {
         terminal.draw(|f| ui(f, app))?;
+        // Redraw periodically even without input so the board never goes stale
+        let timeout = match app.footer_hides_in() {
+            Some(hides_in) if !hides_in.is_zero() => hides_in.min(REDRAW_INTERVAL),
+            _ => REDRAW_INTERVAL,
+        };
+        if !event::poll(timeout)? {
+            continue;
+        }
         if let Event::Key(key) = event::read()? {
             if key.kind == event::KeyEventKind::Release {
                 continue;
             }
+            app.toast = None;
 
             match app.current_screen {
                 app::CurrentScreen::Exiting => match key.code {
@@ -63,9 +189,35 @@ fn run_app<B: Backend>(terminal: &mut Terminal<B>, app: &mut App) -> io::Result<
                     }
                     _ => {}
                 },
+                app::CurrentScreen::Main if app.jump.is_some() => match key.code {
+                    KeyCode::Char(c @ '0'..='9') => {
+                        let digit = c.to_digit(10).unwrap() as usize;
+                        app.jump = app.jump.map(|n| n.saturating_mul(10).saturating_add(digit));
+                    }
+                    KeyCode::Enter => {
+                        if let Some(position) = app.jump.take() {
+                            if (1..=app.notes.len()).contains(&position) {
+                                app.focus_note(position - 1);
+                            }
+                        }
+                    }
+                    _ => app.jump = None,
+                },
                 app::CurrentScreen::Main => match key.code {
+                    KeyCode::Char(c @ '1'..='9') => {
+                        app.jump = c.to_digit(10).map(|digit| digit as usize);
+                    }
+                    KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                        let quit = app.request_quit();
+                        if quit {
+                            return Ok(false);
+                        }
+                    }
                     KeyCode::Char('q') => {
-                        app.current_screen = CurrentScreen::Exiting;
+                        let quit = app.request_quit();
+                        if quit {
+                            return Ok(false);
+                        }
                     }
                     KeyCode::Char('l') => {
                         app.move_focus_right();
@@ -73,15 +225,44 @@ fn run_app<B: Backend>(terminal: &mut Terminal<B>, app: &mut App) -> io::Result<
                     KeyCode::Char('h') => {
                         app.move_focus_left();
                     }
+                    KeyCode::Char('+' | '=') => {
+                        app.resize_focused_note(1);
+                    }
+                    KeyCode::Char('-') => {
+                        app.resize_focused_note(-1);
+                    }
+                    KeyCode::Char('P') => {
+                        if let Some(note) = app.get_focused_note() {
+                            app.move_to(note, 0);
+                        }
+                    }
+                    KeyCode::Char('c') => {
+                        app.hide_completed = !app.hide_completed;
+                    }
+                    KeyCode::Char('z') => {
+                        app.collapsed = !app.collapsed;
+                    }
                     KeyCode::Char(':') => {
                         app.current_screen = CurrentScreen::Command;
                         let res = crate::ui::command_mode(terminal, app);
                         if let Ok(s) = res {
                             match s.as_str() {
                                 ":wq" => return Ok(true),
+                                ":w" => {
+                                    match utils::write_notes_to_file(
+                                        &app.notes,
+                                        &utils::notes_path(),
+                                    ) {
+                                        Ok(()) => app.modified = false,
+                                        Err(err) => {
+                                            let message = format!("Could not save notes: {}", err);
+                                            send_err(&message, terminal, app)?;
+                                        }
+                                    }
+                                }
                                 ":q!" => return Ok(false),
                                 ":help" | ":info" | ":h" | ":i" => {
-                                    send_message("wq - write changes and quit, q! - dicard changes and quit, q - quit, help - display this message", terminal, app)?;
+                                    send_message(HELP, terminal, app)?;
                                 }
                                 ":q" => {
                                     if !app.modified {
@@ -94,6 +275,67 @@ fn run_app<B: Backend>(terminal: &mut Terminal<B>, app: &mut App) -> io::Result<
                                         )?;
                                     }
                                 }
+                                cmd if cmd == ":new" || cmd.starts_with(":new ") => {
+                                    let title = cmd[":new".len()..].trim();
+                                    if title.is_empty() {
+                                        send_err("Usage: :new <title>", terminal, app)?;
+                                    } else if let Err(message) = app.add_note(title.to_string()) {
+                                        send_err(&message, terminal, app)?;
+                                    } else {
+                                        app.focus_note(app.notes.len() - 1);
+                                    }
+                                }
+                                ":open-data" => {
+                                    let path = utils::notes_path();
+                                    let data_dir = path.parent().unwrap_or(&path);
+                                    let spawned = Command::new(&app.config.open_command)
+                                        .arg(data_dir)
+                                        .stdin(Stdio::null())
+                                        .stdout(Stdio::null())
+                                        .stderr(Stdio::null())
+                                        .spawn();
+                                    match spawned {
+                                        // Reaped in the background so it doesn't linger as a zombie
+                                        Ok(mut child) => {
+                                            std::thread::spawn(move || child.wait());
+                                        }
+                                        Err(err) => {
+                                            let message = format!(
+                                                "Could not run {}: {}",
+                                                app.config.open_command, err
+                                            );
+                                            send_err(&message, terminal, app)?;
+                                        }
+                                    }
+                                }
+                                cmd if cmd == ":saveconfig"
+                                    || cmd.starts_with(":saveconfig ")
+                                    || cmd.starts_with(":saveconfig!") =>
+                                {
+                                    let args = &cmd[":saveconfig".len()..];
+                                    let (force, path) = match args.strip_prefix('!') {
+                                        Some(path) => (true, path.trim()),
+                                        None => (false, args.trim()),
+                                    };
+                                    if path.is_empty() {
+                                        send_err("Usage: :saveconfig[!] <path>", terminal, app)?;
+                                    } else if let Err(err) = app.config.save(Path::new(path), force)
+                                    {
+                                        let message = if err.kind() == io::ErrorKind::AlreadyExists
+                                        {
+                                            format!("{}, use :saveconfig! to overwrite", err)
+                                        } else {
+                                            format!("Could not save config: {}", err)
+                                        };
+                                        send_err(&message, terminal, app)?;
+                                    } else {
+                                        send_message(
+                                            &format!("Saved config to {}", path),
+                                            terminal,
+                                            app,
+                                        )?;
+                                    }
+                                }
                                 _ => {
                                     let message = s + " not valid command";
                                     send_err(message.as_str(), terminal, app)?;
@@ -104,9 +346,18 @@ fn run_app<B: Backend>(terminal: &mut Terminal<B>, app: &mut App) -> io::Result<
                     }
                     KeyCode::Char('e') | KeyCode::Enter => {
                         if let Some(note) = app.get_focused_note() {
+                            let was_complete = app.notes[note].is_complete();
                             app.current_screen = CurrentScreen::NoteEdit(note);
                             crate::ui::vim_mode(terminal, app)?;
                             app.current_screen = CurrentScreen::Main;
+                            if app.config.advance_on_complete
+                                && !was_complete
+                                && app.notes[note].is_complete()
+                            {
+                                if let Some(next) = app.next_note_with_work() {
+                                    app.focus_note(next);
+                                }
+                            }
                         }
                     }
                     KeyCode::Char('a') => {
@@ -123,11 +374,14 @@ fn run_app<B: Backend>(terminal: &mut Terminal<B>, app: &mut App) -> io::Result<
                 },
                 app::CurrentScreen::NoteEdit(_) => {}
                 app::CurrentScreen::NewNote => {}
-                app::CurrentScreen::Command => match key.code {
-                    KeyCode::Esc => app.current_screen = CurrentScreen::Main,
-                    _ => {}
-                },
+                app::CurrentScreen::Command => {
+                    if key.code == KeyCode::Esc {
+                        app.current_screen = CurrentScreen::Main
+                    }
+                }
             }
+            // Counted after handling so time spent in the editor or popups is not idle time
+            app.last_input = Instant::now();
         }
     }
 }
@@ -1,10 +1,55 @@
+use crate::utils::{complete_item, indent_level, set_item_status};
 use ratatui::style::Color;
+use std::collections::HashSet;
+use std::ops::Range;
+
+pub const DEFAULT_WIDTH: u16 = 4;
+pub const MIN_WIDTH: u16 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ItemStatus {
+    Todo,
+    Doing,
+    Done,
+}
+
+impl ItemStatus {
+    pub fn marker(&self) -> &'static str {
+        match self {
+            Self::Todo => "[ ]",
+            Self::Doing => "[~]",
+            Self::Done => "[x]",
+        }
+    }
+
+    pub fn next(&self) -> ItemStatus {
+        match self {
+            Self::Todo => Self::Doing,
+            Self::Doing => Self::Done,
+            Self::Done => Self::Todo,
+        }
+    }
+
+    // Splits an item line into its indentation, checkbox status and text
+    pub fn split(line: &str) -> (&str, Option<ItemStatus>, &str) {
+        let rest = line.trim_start();
+        let indent = &line[..line.len() - rest.len()];
+        for status in [Self::Todo, Self::Doing, Self::Done] {
+            if let Some(text) = rest.strip_prefix(status.marker()) {
+                return (indent, Some(status), text.trim_start());
+            }
+        }
+        (indent, None, rest)
+    }
+}
 
 pub struct Note {
     pub title: String,
     pub items: Vec<String>,
     pub focused: bool,
+    #[allow(dead_code)]
     pub color: Color,
+    pub width: u16,
 }
 
 impl Note {
@@ -14,20 +59,148 @@ impl Note {
             items: Vec::new(),
             focused: false,
             color: Color::LightBlue,
+            width: DEFAULT_WIDTH,
+        }
+    }
+
+    pub fn get_note_text_vec(&self) -> Vec<String> {
+        self.items.clone()
+    }
+
+    pub fn completed_count(&self) -> usize {
+        self.items
+            .iter()
+            .filter(|item| ItemStatus::split(item).1 == Some(ItemStatus::Done))
+            .count()
+    }
+
+    pub fn total_count(&self) -> usize {
+        self.items
+            .iter()
+            .filter(|item| ItemStatus::split(item).1.is_some())
+            .count()
+    }
+
+    pub fn completion_ratio(&self) -> Option<f64> {
+        match self.total_count() {
+            0 => None,
+            total => Some(self.completed_count() as f64 / total as f64),
         }
     }
 
-    pub fn get_note_text(&self) -> String {
-        let mut ret = String::new();
-        for item in &self.items {
-            ret += &item;
-            ret += "\n";
+    pub fn is_complete(&self) -> bool {
+        let total = self.total_count();
+        total > 0 && self.completed_count() == total
+    }
+
+    pub fn duplicate_items(&self, ignore_case: bool) -> Vec<bool> {
+        let mut seen = HashSet::new();
+        self.items
+            .iter()
+            .map(|item| {
+                let text = crate::utils::item_text(item).trim();
+                let key = if ignore_case {
+                    text.to_lowercase()
+                } else {
+                    text.to_string()
+                };
+                !text.is_empty() && !seen.insert(key)
+            })
+            .collect()
+    }
+
+    // The item at index together with the more deeply indented items below it
+    pub fn child_range(&self, index: usize) -> Range<usize> {
+        let level = indent_level(&self.items[index]);
+        let end = self.items[index + 1..]
+            .iter()
+            .position(|item| indent_level(item) <= level)
+            .map_or(self.items.len(), |offset| index + 1 + offset);
+        index..end
+    }
+
+    // Moves an item and its children to just before the item at `to`, or to the end if `to` is
+    // the item count. Returns the item's new index, or None if `to` is out of bounds or inside
+    // the moved children
+    pub fn move_item(&mut self, from: usize, to: usize) -> Option<usize> {
+        if from >= self.items.len() || to > self.items.len() {
+            return None;
         }
-        ret
+        let range = self.child_range(from);
+        if range.start < to && to < range.end {
+            return None;
+        }
+        let len = range.len();
+        let group: Vec<String> = self.items.drain(range.clone()).collect();
+        let at = if to >= range.end { to - len } else { to };
+        self.items.splice(at..at, group);
+        Some(at)
     }
 
-    pub fn get_note_text_vec(&self) -> Vec<String> {
-        self.items.clone()
+    // Swaps an item with the next item at the same level, keeping both with their children
+    pub fn move_item_down(&mut self, index: usize) -> Option<usize> {
+        let next = self.child_range(index).end;
+        let sibling = self.items.get(next)?;
+        if indent_level(sibling) != indent_level(&self.items[index]) {
+            return None;
+        }
+        self.move_item(index, self.child_range(next).end)
+    }
+
+    pub fn move_item_up(&mut self, index: usize) -> Option<usize> {
+        let level = indent_level(&self.items[index]);
+        let previous = (0..index)
+            .rev()
+            .find(|&i| indent_level(&self.items[i]) <= level)?;
+        if indent_level(&self.items[previous]) != level {
+            return None;
+        }
+        self.move_item(index, previous)
+    }
+
+    // Cycles an item's status. Marking it done or back to todo does the same to its children,
+    // then each parent above it is done exactly when everything under it is
+    pub fn toggle_item_cascading(&mut self, index: usize) -> Option<usize> {
+        let item = self.items.get_mut(index)?;
+        *item = complete_item(std::mem::take(item));
+        let status = ItemStatus::split(&self.items[index]).1;
+        if let Some(status @ (ItemStatus::Done | ItemStatus::Todo)) = status {
+            for i in self.child_range(index).skip(1) {
+                self.items[i] = set_item_status(std::mem::take(&mut self.items[i]), status);
+            }
+        }
+        self.update_parents(index);
+        Some(index)
+    }
+
+    // The closest item above with a smaller indent
+    fn parent_of(&self, index: usize) -> Option<usize> {
+        let level = indent_level(&self.items[index]);
+        (0..index)
+            .rev()
+            .find(|&i| indent_level(&self.items[i]) < level)
+    }
+
+    fn update_parents(&mut self, index: usize) {
+        let mut child = index;
+        while let Some(parent) = self.parent_of(child) {
+            let mut statuses = self
+                .child_range(parent)
+                .skip(1)
+                .filter_map(|i| ItemStatus::split(&self.items[i]).1)
+                .peekable();
+            let all_done = statuses.peek().is_some() && statuses.all(|s| s == ItemStatus::Done);
+            let new_status = match ItemStatus::split(&self.items[parent]).1 {
+                Some(ItemStatus::Done) if !all_done => Some(ItemStatus::Todo),
+                Some(status) if all_done && status != ItemStatus::Done => Some(ItemStatus::Done),
+                _ => None,
+            };
+            if let Some(status) = new_status {
+                let item = std::mem::take(&mut self.items[parent]);
+                self.items[parent] = set_item_status(item, status);
+            }
+            child = parent;
+        }
     }
 
     pub fn is_focused(&self) -> bool {
@@ -42,3 +215,218 @@ impl Note {
         self.focused = false;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn note(items: &[&str]) -> Note {
+        let mut note = Note::new("Test".to_string());
+        note.items = items.iter().map(|item| item.to_string()).collect();
+        note
+    }
+
+    // Two top-level items with nested children, and one without
+    fn board_note() -> Note {
+        note(&[
+            "[ ] A",
+            "\t[ ] a1",
+            "\t\t[ ] a1x",
+            "[ ] B",
+            "\t[ ] b1",
+            "[ ] C",
+        ])
+    }
+
+    #[test]
+    fn completing_a_grandchild_completes_every_ancestor() {
+        let mut note = note(&["[ ] G", "\t[ ] P", "\t\t[~] c", "[ ] other"]);
+        assert_eq!(note.toggle_item_cascading(2), Some(2));
+        assert_eq!(note.items, ["[x] G", "\t[x] P", "\t\t[x] c", "[ ] other"]);
+    }
+
+    #[test]
+    fn unchecking_a_grandchild_reverts_every_ancestor() {
+        let mut note = note(&["[x] G", "\t[x] P", "\t\t[x] c", "\t[x] sibling"]);
+        note.toggle_item_cascading(2);
+        assert_eq!(
+            note.items,
+            ["[ ] G", "\t[ ] P", "\t\t[ ] c", "\t[x] sibling"]
+        );
+    }
+
+    #[test]
+    fn parent_waits_for_every_child() {
+        let mut note = note(&["[ ] P", "\t[~] a", "\t[ ] b"]);
+        note.toggle_item_cascading(1);
+        assert_eq!(note.items, ["[ ] P", "\t[x] a", "\t[ ] b"]);
+    }
+
+    #[test]
+    fn doing_parent_completes_with_its_children() {
+        let mut note = note(&["[~] P", "\t[~] c"]);
+        note.toggle_item_cascading(1);
+        assert_eq!(note.items, ["[x] P", "\t[x] c"]);
+    }
+
+    #[test]
+    fn doing_parent_stays_doing_while_children_are_open() {
+        let mut note = note(&["[~] P", "\t[ ] a", "\t[ ] b"]);
+        note.toggle_item_cascading(1);
+        assert_eq!(note.items, ["[~] P", "\t[~] a", "\t[ ] b"]);
+    }
+
+    #[test]
+    fn lines_without_a_marker_are_ignored() {
+        let mut note = note(&["[ ] P", "\tsome notes", "\t[~] c", "\t\tmore notes"]);
+        note.toggle_item_cascading(2);
+        assert_eq!(
+            note.items,
+            ["[x] P", "\tsome notes", "\t[x] c", "\t\tmore notes"]
+        );
+        note.toggle_item_cascading(2);
+        assert_eq!(
+            note.items,
+            ["[ ] P", "\tsome notes", "\t[ ] c", "\t\tmore notes"]
+        );
+    }
+
+    #[test]
+    fn completing_a_parent_completes_its_children() {
+        let mut note = note(&["[~] P", "\t[ ] a", "\t\t[~] a1", "\tnotes", "[ ] other"]);
+        note.toggle_item_cascading(0);
+        assert_eq!(
+            note.items,
+            ["[x] P", "\t[x] a", "\t\t[x] a1", "\tnotes", "[ ] other"]
+        );
+        note.toggle_item_cascading(0);
+        assert_eq!(
+            note.items,
+            ["[ ] P", "\t[ ] a", "\t\t[ ] a1", "\tnotes", "[ ] other"]
+        );
+    }
+
+    #[test]
+    fn child_range_covers_nested_children() {
+        let note = board_note();
+        assert_eq!(note.child_range(0), 0..3);
+        assert_eq!(note.child_range(1), 1..3);
+        assert_eq!(note.child_range(2), 2..3);
+        assert_eq!(note.child_range(3), 3..5);
+        assert_eq!(note.child_range(5), 5..6);
+    }
+
+    #[test]
+    fn move_item_carries_its_children() {
+        let mut note = board_note();
+        assert_eq!(note.move_item(3, 0), Some(0));
+        assert_eq!(
+            note.items,
+            [
+                "[ ] B",
+                "\t[ ] b1",
+                "[ ] A",
+                "\t[ ] a1",
+                "\t\t[ ] a1x",
+                "[ ] C"
+            ]
+        );
+    }
+
+    #[test]
+    fn move_item_to_the_end() {
+        let mut note = board_note();
+        assert_eq!(note.move_item(0, 6), Some(3));
+        assert_eq!(
+            note.items,
+            [
+                "[ ] B",
+                "\t[ ] b1",
+                "[ ] C",
+                "[ ] A",
+                "\t[ ] a1",
+                "\t\t[ ] a1x"
+            ]
+        );
+    }
+
+    #[test]
+    fn move_item_to_the_end_of_its_own_group_is_a_no_op() {
+        let mut note = board_note();
+        assert_eq!(note.move_item(0, 3), Some(0));
+        assert_eq!(note.move_item(0, 0), Some(0));
+        assert_eq!(note.items, board_note().items);
+    }
+
+    #[test]
+    fn move_item_into_its_own_children_fails() {
+        let mut note = board_note();
+        assert_eq!(note.move_item(0, 1), None);
+        assert_eq!(note.move_item(0, 2), None);
+        assert_eq!(note.items, board_note().items);
+    }
+
+    #[test]
+    fn move_item_out_of_bounds_fails() {
+        let mut note = board_note();
+        assert_eq!(note.move_item(6, 0), None);
+        assert_eq!(note.move_item(0, 7), None);
+        assert_eq!(note.items, board_note().items);
+    }
+
+    #[test]
+    fn move_item_down_swaps_with_the_next_sibling_group() {
+        let mut note = board_note();
+        assert_eq!(note.move_item_down(0), Some(2));
+        assert_eq!(
+            note.items,
+            [
+                "[ ] B",
+                "\t[ ] b1",
+                "[ ] A",
+                "\t[ ] a1",
+                "\t\t[ ] a1x",
+                "[ ] C"
+            ]
+        );
+        // The last item at a level has nowhere to go
+        assert_eq!(note.move_item_down(5), None);
+        assert_eq!(note.move_item_down(3), None);
+    }
+
+    #[test]
+    fn move_item_up_swaps_with_the_previous_sibling_group() {
+        let mut note = board_note();
+        assert_eq!(note.move_item_up(5), Some(3));
+        assert_eq!(
+            note.items,
+            [
+                "[ ] A",
+                "\t[ ] a1",
+                "\t\t[ ] a1x",
+                "[ ] C",
+                "[ ] B",
+                "\t[ ] b1"
+            ]
+        );
+        // The first item at a level stays under its parent
+        assert_eq!(note.move_item_up(0), None);
+        assert_eq!(note.move_item_up(1), None);
+    }
+
+    #[test]
+    fn nested_items_move_within_their_parent() {
+        let mut note = note(&["[ ] A", "\t[ ] x", "\t\t[ ] x1", "\t[ ] y", "[ ] B"]);
+        assert_eq!(note.move_item_up(3), Some(1));
+        assert_eq!(
+            note.items,
+            ["[ ] A", "\t[ ] y", "\t[ ] x", "\t\t[ ] x1", "[ ] B"]
+        );
+        assert_eq!(note.move_item_down(2), None);
+        assert_eq!(note.move_item_down(1), Some(3));
+        assert_eq!(
+            note.items,
+            ["[ ] A", "\t[ ] x", "\t\t[ ] x1", "\t[ ] y", "[ ] B"]
+        );
+    }
+}
@@ -1,26 +1,38 @@
 use crate::app::{App, CurrentScreen};
+use crate::config::LayoutConfig;
+use crate::note::{ItemStatus, Note};
+use crate::utils::{indent_level, truncate_to};
 use crate::vim::{Mode, Transition, Vim};
-use crossterm::event::{read, KeyCode, KeyEventState, KeyModifiers};
+use crossterm::event::{poll, read, Event, KeyCode, KeyEvent, KeyEventState, KeyModifiers};
 use ratatui::backend::Backend;
-use ratatui::style::Stylize;
 use ratatui::Terminal;
 use ratatui::{
     layout::{Alignment, Constraint, Direction, Layout, Rect},
     style::{Color, Style},
     text::{Line, Span, Text},
-    widgets::{block::Title, Block, BorderType, Borders, Clear, Paragraph, Wrap},
+    widgets::{
+        block::{Position, Title},
+        Block, BorderType, Borders, Clear, Gauge, Paragraph, Wrap,
+    },
     Frame,
 };
 use std::io;
-use tui_textarea::{Input, Key, TextArea};
+use std::time::Duration;
+use tui_textarea::{CursorMove, Input, Key, TextArea};
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
 
 pub fn ui(f: &mut Frame, app: &App) {
+    let footer_hidden = app.footer_hidden();
     let chunks = Layout::default()
         .direction(Direction::Vertical)
         .constraints([
             Constraint::Min(3),
             Constraint::Percentage(100),
-            Constraint::Min(3),
+            if footer_hidden {
+                Constraint::Length(0)
+            } else {
+                Constraint::Min(3)
+            },
         ])
         .split(f.size());
 
@@ -28,9 +40,19 @@ pub fn ui(f: &mut Frame, app: &App) {
         .borders(Borders::TOP | Borders::BOTTOM)
         .style(Style::default().fg(Color::LightBlue));
 
+    let mut header = String::from("keepTUIt");
+    if app.modified {
+        header += " [+]";
+    }
+    let header_color = if app.read_only {
+        header += " (read-only: changes cannot be saved to the notes file)";
+        Color::LightRed
+    } else {
+        Color::LightYellow
+    };
     let title = Paragraph::new(Text::styled(
-        "keepTUIt",
-        Style::default().fg(Color::LightYellow),
+        truncate_to(&header, chunks[0].width as usize),
+        Style::default().fg(header_color),
     ))
     .block(title_block)
     .alignment(Alignment::Center);
@@ -40,23 +62,53 @@ pub fn ui(f: &mut Frame, app: &App) {
     match app.current_screen {
         CurrentScreen::Main | CurrentScreen::Command => {
             let number_notes: usize = app.notes.len();
+            let total_width: u32 = app.notes.iter().map(|note| note.width as u32).sum();
 
-            // let constraint_percent: u16 = 100 / (number_notes as u16);
             let note_chunks = Layout::default()
                 .direction(Direction::Horizontal)
-                .constraints(vec![
-                    Constraint::Ratio(1, number_notes as u32);
-                    number_notes
-                ])
+                .spacing(u16::from(app.config.layout.note_gap))
+                .constraints(
+                    app.notes
+                        .iter()
+                        .map(|note| Constraint::Ratio(note.width as u32, total_width)),
+                )
                 .split(chunks[1]);
 
             let active_color = Color::Green;
+            let marker_width = if app.config.layout.align_items {
+                app.config.layout.marker_width()
+            } else {
+                0
+            };
 
             for i in 0..number_notes {
                 let note = app.notes.get(i).unwrap();
 
+                let mut title = if app.config.show_ids {
+                    format!("#{} {}", i + 1, note.title)
+                } else {
+                    note.title.clone()
+                };
+                if app.jump.is_some() {
+                    title = format!("{}: {}", i + 1, title);
+                }
+                if app.hide_completed && note.total_count() > 0 {
+                    let left = note.total_count() - note.completed_count();
+                    if left == 0 {
+                        title += " (done)";
+                    } else {
+                        title += &format!(" ({} left)", left);
+                    }
+                }
+
                 let mut note_block = Block::default()
-                    .title(Title::from(note.title.clone()).alignment(Alignment::Center))
+                    .title(
+                        Title::from(truncate_to(
+                            &title,
+                            note_chunks[i].width.saturating_sub(2) as usize,
+                        ))
+                        .alignment(Alignment::Center),
+                    )
                     .borders(Borders::ALL)
                     .border_type(BorderType::Rounded);
 
@@ -64,8 +116,72 @@ pub fn ui(f: &mut Frame, app: &App) {
                     note_block = note_block.border_style(Style::default().fg(active_color));
                 }
 
-                let note_text = Paragraph::new(note.get_note_text()).block(note_block);
-                f.render_widget(note_text, note_chunks[i]);
+                if app.collapsed {
+                    let title_area = Rect {
+                        height: note_chunks[i].height.min(2),
+                        ..note_chunks[i]
+                    };
+                    f.render_widget(note_block, title_area);
+                    continue;
+                }
+
+                let note_text = if app.config.layout.collapse_completed
+                    && note.is_complete()
+                    && !note.is_focused()
+                {
+                    Text::styled("done", Style::default().fg(Color::Green))
+                } else {
+                    let duplicates = note.duplicate_items(app.config.layout.duplicates_ignore_case);
+                    let (items, duplicates): (Vec<&String>, Vec<bool>) = note
+                        .items
+                        .iter()
+                        .zip(duplicates)
+                        .filter(|(item, _)| {
+                            !app.hide_completed
+                                || ItemStatus::split(item).1 != Some(ItemStatus::Done)
+                        })
+                        .unzip();
+                    // Connect only the items that are shown
+                    let tree = app.config.layout.tree_items.then(|| tree_prefixes(&items));
+                    Text::from(
+                        items
+                            .iter()
+                            .zip(duplicates)
+                            .enumerate()
+                            .map(|(j, (item, duplicate))| {
+                                let tree_prefix = tree.as_ref().map(|tree| tree[j].as_str());
+                                let item = display_item(
+                                    item,
+                                    &app.config.layout,
+                                    marker_width,
+                                    tree_prefix,
+                                );
+                                if duplicate && app.config.layout.highlight_duplicates {
+                                    Line::styled(item, Style::default().fg(Color::LightRed))
+                                } else {
+                                    Line::from(item)
+                                }
+                            })
+                            .collect::<Vec<Line>>(),
+                    )
+                };
+                let ratio = note.completion_ratio();
+                if let (true, Some(ratio)) = (app.config.layout.progress_bar, ratio) {
+                    let inner = note_block.inner(note_chunks[i]);
+                    let card_chunks = Layout::default()
+                        .direction(Direction::Vertical)
+                        .constraints([Constraint::Min(0), Constraint::Length(1)])
+                        .split(inner);
+                    let progress = Gauge::default()
+                        .gauge_style(Style::default().fg(Color::Green))
+                        .ratio(ratio);
+                    f.render_widget(note_block, note_chunks[i]);
+                    f.render_widget(Paragraph::new(note_text), card_chunks[0]);
+                    f.render_widget(progress, card_chunks[1]);
+                } else {
+                    let note_text = Paragraph::new(note_text).block(note_block);
+                    f.render_widget(note_text, note_chunks[i]);
+                }
             }
         }
         _ => {}
@@ -95,35 +211,51 @@ pub fn ui(f: &mut Frame, app: &App) {
     }
     .to_owned()];
 
-    let mode_footer = Paragraph::new(Line::from(current_navigation_text))
-        .block(Block::default().borders(Borders::ALL));
+    // Narrow terminals get a single footer line with only the most used hints
+    let compact = chunks[2].width < app.config.layout.compact_footer_width;
 
     let current_key_hint = {
         match app.current_screen {
+            CurrentScreen::Main if app.jump.is_some() => Span::styled(
+                format!(
+                    "Jump to note {} <ENTER> jump, <ESC> cancel",
+                    app.jump.unwrap_or_default()
+                ),
+                Style::default().fg(Color::Red),
+            ),
+            CurrentScreen::Main if compact => Span::styled(
+                "[q]uit [e]dit [a]dd [:]cmd",
+                Style::default().fg(Color::Red),
+            ),
+            CurrentScreen::NoteEdit(_) if compact => {
+                Span::styled("(q) to quit", Style::default().fg(Color::Red))
+            }
             CurrentScreen::Main => Span::styled(
-                "[q]uit [e]dit [D]elete [a]dd note <h> left <l> right",
-                Style::default().fg(Color::Red.into()),
+                "[q]uit/<C-c> [e]dit [D]elete [a]dd note <h> left <l> right <+/-> width <z> collapse <P> to front <c> hide done <1-9> jump",
+                Style::default().fg(Color::Red),
             ),
             CurrentScreen::NoteEdit(_) => Span::styled(
-                "VIM keybinds (Tab) to indent checkbox (Alt-Tab) to unindent, (q) to quit",
-                Style::default().fg(Color::Red.into()),
+                "VIM keybinds (Tab) to indent checkbox (Alt-Tab) to unindent, (Enter) to cycle status, (Alt-j/k) to move item, (gy) to yank item text, (q) to quit",
+                Style::default().fg(Color::Red),
             ),
             CurrentScreen::Exiting => {
-                Span::styled("<Esc> to cancel", Style::default().fg(Color::Red.into()))
+                Span::styled("<Esc> to cancel", Style::default().fg(Color::Red))
             }
             CurrentScreen::NewNote => Span::styled(
                 "<ESC> cancel, <ENTER> accept ",
-                Style::default().fg(Color::Red.into()),
+                Style::default().fg(Color::Red),
             ),
             CurrentScreen::Command => Span::styled(
                 "<ESC> cancel, <ENTER> accept ",
-                Style::default().fg(Color::Red.into()),
+                Style::default().fg(Color::Red),
             ),
         }
     };
 
-    let key_notes_footer =
-        Paragraph::new(Line::from(current_key_hint)).block(Block::default().borders(Borders::ALL));
+    let current_key_hint = match &app.toast {
+        Some(message) => Span::styled(message.as_str(), Style::default().fg(Color::LightYellow)),
+        None => current_key_hint,
+    };
 
     if let CurrentScreen::Exiting = &app.current_screen {
         let popup_block = Block::default()
@@ -131,25 +263,86 @@ pub fn ui(f: &mut Frame, app: &App) {
             .borders(Borders::ALL)
             .style(Style::default());
 
-        let exit_text = Text::styled(
-            "Would you like to save changes made to keepTUIt? (y/n)",
-            Style::default().fg(Color::Red.into()),
-        );
+        let exit_message = "Would you like to save changes made to keepTUIt? (y/n)";
+        let exit_text = Text::styled(exit_message, Style::default().fg(Color::Red));
 
         let exit_paragraph = Paragraph::new(exit_text)
             .block(popup_block)
             .wrap(Wrap { trim: false })
             .centered();
 
-        let area = centered_rect(30, 50, chunks[1]);
+        let area = popup_rect(exit_message, chunks[1]);
+        f.render_widget(Clear, area);
         f.render_widget(exit_paragraph, area);
     }
-    let footer_chunk = Layout::default()
-        .direction(Direction::Horizontal)
-        .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
-        .split(chunks[2]);
-    f.render_widget(mode_footer, footer_chunk[0]);
-    f.render_widget(key_notes_footer, footer_chunk[1]);
+    if footer_hidden {
+        return;
+    }
+    if compact {
+        let mut footer_text = current_navigation_text;
+        footer_text.push(Span::raw(" | "));
+        footer_text.push(current_key_hint);
+        let footer =
+            Paragraph::new(Line::from(footer_text)).block(Block::default().borders(Borders::ALL));
+        f.render_widget(footer, chunks[2]);
+    } else {
+        let mode_footer = Paragraph::new(Line::from(current_navigation_text))
+            .block(Block::default().borders(Borders::ALL));
+        let key_notes_footer = Paragraph::new(Line::from(current_key_hint))
+            .block(Block::default().borders(Borders::ALL));
+        let footer_chunk = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
+            .split(chunks[2]);
+        f.render_widget(mode_footer, footer_chunk[0]);
+        f.render_widget(key_notes_footer, footer_chunk[1]);
+    }
+}
+
+// Renders an item with the configured checkbox markers, padding each marker to `marker_width`.
+// A tree prefix, if given, replaces the item's indentation.
+fn display_item(
+    item: &str,
+    layout: &LayoutConfig,
+    marker_width: usize,
+    tree_prefix: Option<&str>,
+) -> String {
+    let (indent, status, text) = ItemStatus::split(item);
+    let indent = tree_prefix.unwrap_or(indent);
+    match status {
+        Some(status) => {
+            let marker = layout.marker(status);
+            let padding = " ".repeat(marker_width.saturating_sub(marker.width()));
+            format!("{}{}{} {}", indent, marker, padding, text)
+        }
+        None => format!("{}{}", indent, text),
+    }
+}
+
+// Builds tree connectors (├─, └─, │) for each item from its indentation level
+fn tree_prefixes(items: &[&String]) -> Vec<String> {
+    let levels: Vec<usize> = items.iter().map(|item| indent_level(item)).collect();
+    levels
+        .iter()
+        .enumerate()
+        .map(|(i, &level)| {
+            let mut prefix = String::new();
+            for depth in 1..=level {
+                // Whether another item at this depth follows before the branch closes
+                let continues = levels[i + 1..]
+                    .iter()
+                    .take_while(|&&later| later >= depth)
+                    .any(|&later| later == depth);
+                prefix.push_str(match (depth == level, continues) {
+                    (true, true) => "├─",
+                    (true, false) => "└─",
+                    (false, true) => "│ ",
+                    (false, false) => "  ",
+                });
+            }
+            prefix
+        })
+        .collect()
 }
 
 fn centered_rect(percent_x: u16, percent_y: u16, r: Rect) -> Rect {
@@ -171,60 +364,126 @@ fn centered_rect(percent_x: u16, percent_y: u16, r: Rect) -> Rect {
         ])
         .split(popup_layout[1])[1]
 }
+fn wrapped_height(text: &str, width: u16) -> u16 {
+    let width = width.max(1) as usize;
+    let mut lines = 1;
+    let mut line_len = 0;
+    for word in text.split_whitespace() {
+        let word_len = word.width();
+        if line_len > 0 && line_len + 1 + word_len <= width {
+            line_len += 1 + word_len;
+            continue;
+        }
+        if line_len > 0 {
+            lines += 1;
+            line_len = 0;
+        }
+        // Words longer than the line are broken across several lines
+        for c in word.chars() {
+            let c_len = c.width().unwrap_or(0);
+            if line_len > 0 && line_len + c_len > width {
+                lines += 1;
+                line_len = 0;
+            }
+            line_len += c_len;
+        }
+    }
+    lines
+}
+
+fn popup_rect(text: &str, r: Rect) -> Rect {
+    let max_width = (r.width / 2).max(24).min(r.width);
+    let mut width = (text.width() as u16 + 2).min(max_width);
+    // Take the full width before falling back to scrolling
+    if wrapped_height(text, width.saturating_sub(2)) + 2 > r.height {
+        width = r.width;
+    }
+    let height = (wrapped_height(text, width.saturating_sub(2)) + 2).min(r.height);
+    Rect {
+        x: r.x + (r.width - width) / 2,
+        y: r.y + (r.height - height) / 2,
+        width,
+        height,
+    }
+}
+
+fn show_message<B: Backend>(
+    message: &str,
+    color: Color,
+    terminal: &mut Terminal<B>,
+    app: &mut App,
+) -> io::Result<()> {
+    let text = message.to_string() + " - Press any key to continue";
+    let mut scroll = 0;
+    loop {
+        let mut max_scroll = 0;
+        terminal.draw(|f| max_scroll = draw_message(f, app, &text, color, scroll))?;
+        match read()? {
+            Event::Key(KeyEvent {
+                code: KeyCode::Char('j') | KeyCode::Down,
+                ..
+            }) if max_scroll > 0 => scroll = (scroll + 1).min(max_scroll),
+            Event::Key(KeyEvent {
+                code: KeyCode::Char('k') | KeyCode::Up,
+                ..
+            }) if max_scroll > 0 => scroll = scroll.saturating_sub(1),
+            _ => return Ok(()),
+        }
+    }
+}
+
+// Draws the message over the board and returns how far it can be scrolled
+fn draw_message(f: &mut Frame, app: &App, text: &str, color: Color, scroll: u16) -> u16 {
+    ui(f, app);
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Min(3),
+            Constraint::Percentage(100),
+            Constraint::Min(3),
+        ])
+        .split(f.size());
+    // Messages that do not fit in the footer are shown in a popup instead
+    let area = if text.width() as u16 + 2 <= chunks[2].width {
+        chunks[2]
+    } else {
+        popup_rect(text, f.size())
+    };
+    let max_scroll =
+        (wrapped_height(text, area.width.saturating_sub(2)) + 2).saturating_sub(area.height);
+    let mut block = Block::default().borders(Borders::ALL);
+    if max_scroll > 0 {
+        block = block.title(
+            Title::from("j/k to scroll")
+                .position(Position::Bottom)
+                .alignment(Alignment::Right),
+        );
+    }
+    let message_block = Paragraph::new(Span::styled(text, Style::default().fg(color)))
+        .block(block)
+        .wrap(Wrap { trim: false })
+        .scroll((scroll.min(max_scroll), 0));
+    f.render_widget(Clear, area);
+    f.render_widget(message_block, area);
+    max_scroll
+}
+
 pub fn send_message<B: Backend>(
     message: &str,
     terminal: &mut Terminal<B>,
     app: &mut App,
 ) -> io::Result<()> {
-    let text = Span::styled(
-        message.to_string() + " - Press any key to continue",
-        Style::default().fg(Color::LightBlue.into()),
-    );
-    terminal.draw(|f| {
-        ui(f, app);
-        let chunks = Layout::default()
-            .direction(Direction::Vertical)
-            .constraints([
-                Constraint::Min(3),
-                Constraint::Percentage(100),
-                Constraint::Min(3),
-            ])
-            .split(f.size());
-        let err_block =
-            Paragraph::new(Line::from(text)).block(Block::default().borders(Borders::ALL));
-        f.render_widget(Clear, chunks[2]);
-        f.render_widget(err_block, chunks[2]);
-    })?;
-    read()?;
-    Ok(())
+    show_message(message, Color::LightBlue, terminal, app)
 }
+
 pub fn send_err<B: Backend>(
     message: &str,
     terminal: &mut Terminal<B>,
     app: &mut App,
 ) -> io::Result<()> {
-    let text = Span::styled(
-        message.to_string() + " - Press any key to continue",
-        Style::default().fg(Color::LightRed.into()),
-    );
-    terminal.draw(|f| {
-        ui(f, app);
-        let chunks = Layout::default()
-            .direction(Direction::Vertical)
-            .constraints([
-                Constraint::Min(3),
-                Constraint::Percentage(100),
-                Constraint::Min(3),
-            ])
-            .split(f.size());
-        let err_block =
-            Paragraph::new(Line::from(text)).block(Block::default().borders(Borders::ALL));
-        f.render_widget(Clear, chunks[2]);
-        f.render_widget(err_block, chunks[2]);
-    })?;
-    read()?;
-    Ok(())
+    show_message(message, Color::LightRed, terminal, app)
 }
+
 pub fn command_mode<B: Backend>(terminal: &mut Terminal<B>, app: &mut App) -> io::Result<String> {
     let mut textarea = TextArea::default();
     textarea.set_placeholder_text("cmd");
@@ -256,9 +515,7 @@ pub fn command_mode<B: Backend>(terminal: &mut Terminal<B>, app: &mut App) -> io
             f.render_widget(widget, chunks[2]);
         })?;
         match crossterm::event::read()?.into() {
-            Input { key: Key::Esc, .. } => {
-                return Err(io::Error::new(io::ErrorKind::Other, "escape"))
-            }
+            Input { key: Key::Esc, .. } => return Err(io::Error::other("escape")),
             Input {
                 key: Key::Enter, ..
             } => {
@@ -287,10 +544,10 @@ pub fn new_note<B: Backend>(terminal: &mut Terminal<B>, app: &mut App) -> io::Re
             Input { key: Key::Esc, .. } => break,
             Input {
                 key: Key::Enter, ..
-            } => {
-                app.add_note(textarea.lines().to_vec().concat());
-                break;
-            }
+            } => match app.add_note(textarea.lines().to_vec().concat()) {
+                Ok(()) => break,
+                Err(message) => send_err(&message, terminal, app)?,
+            },
             input => {
                 // TextArea::input returns if the input modified its text
                 textarea.input(input);
@@ -300,38 +557,346 @@ pub fn new_note<B: Backend>(terminal: &mut Terminal<B>, app: &mut App) -> io::Re
     Ok(())
 }
 
+// Reads the next input. An Esc followed by a key within `esc_timeout` is the start of an escape
+// sequence rather than a standalone Esc, so it is read as that key with Alt held.
+fn read_input(esc_timeout: Duration) -> io::Result<Input> {
+    let event = read()?;
+    if let Event::Key(KeyEvent {
+        code: KeyCode::Esc, ..
+    }) = event
+    {
+        if !esc_timeout.is_zero() && poll(esc_timeout)? {
+            return Ok(match read()? {
+                Event::Key(mut key) => {
+                    key.modifiers |= KeyModifiers::ALT;
+                    key.into()
+                }
+                _ => event.into(),
+            });
+        }
+    }
+    Ok(event.into())
+}
+
+// The text area editing a note, along with the text areas it replaced. Item moves and cascading
+// toggles build a new text area, because replacing the text in place would take two undo steps.
+// Undo continues into the replaced text area once the current one runs out of history
+struct NoteEditor<'a> {
+    text_area: TextArea<'a>,
+    undone: usize, // Undos in the current text area that redo may still replay
+    earlier: Vec<TextArea<'a>>,
+    later: Vec<(TextArea<'a>, usize)>,
+}
+
+impl<'a> NoteEditor<'a> {
+    fn new(lines: Vec<String>) -> Self {
+        Self {
+            text_area: TextArea::new(lines),
+            undone: 0,
+            earlier: Vec::new(),
+            later: Vec::new(),
+        }
+    }
+
+    fn transition(&mut self, vim: &Vim, input: Input, note: &mut Note) -> Transition {
+        let before =
+            (self.undone > 0 || !self.later.is_empty()).then(|| self.text_area.lines().to_vec());
+        let transition = vim.transition(input, &mut self.text_area);
+        match transition {
+            Transition::Undo => self.undo(),
+            Transition::Redo => self.redo(),
+            Transition::MoveItemDown => self.edit_item(note, Note::move_item_down),
+            Transition::MoveItemUp => self.edit_item(note, Note::move_item_up),
+            Transition::ToggleItem => self.edit_item(note, Note::toggle_item_cascading),
+            // Any other change starts a new history, like it does inside a text area
+            _ => {
+                if before.is_some_and(|lines| lines != self.text_area.lines()) {
+                    self.undone = 0;
+                    self.later.clear();
+                }
+            }
+        }
+        transition
+    }
+
+    fn undo(&mut self) {
+        if self.text_area.undo() {
+            self.undone += 1;
+        } else if let Some(earlier) = self.earlier.pop() {
+            let replaced = self.replace(earlier);
+            self.later.push((replaced, self.undone));
+            self.undone = 0;
+        }
+    }
+
+    fn redo(&mut self) {
+        if self.undone > 0 {
+            self.text_area.redo();
+            self.undone -= 1;
+        } else if let Some((later, undone)) = self.later.pop() {
+            let replaced = self.replace(later);
+            self.earlier.push(replaced);
+            self.undone = undone;
+        }
+    }
+
+    // Applies an item edit to the note and continues in a new text area with the cursor on the item
+    fn edit_item(&mut self, note: &mut Note, edit: fn(&mut Note, usize) -> Option<usize>) {
+        let (row, col) = self.text_area.cursor();
+        note.items = self.text_area.lines().to_vec();
+        if let Some(row) = edit(note, row) {
+            let mut edited = TextArea::new(note.items.clone());
+            edited.move_cursor(CursorMove::Jump(row as u16, col as u16));
+            let replaced = self.replace(edited);
+            self.earlier.push(replaced);
+            self.undone = 0;
+            self.later.clear();
+        }
+    }
+
+    // Swaps in another text area, carrying over the yank buffer and styling
+    fn replace(&mut self, mut text_area: TextArea<'a>) -> TextArea<'a> {
+        text_area.set_yank_text(self.text_area.yank_text());
+        if let Some(block) = self.text_area.block() {
+            text_area.set_block(block.clone());
+        }
+        text_area.set_cursor_style(self.text_area.cursor_style());
+        std::mem::replace(&mut self.text_area, text_area)
+    }
+}
+
 pub fn vim_mode<B: Backend>(terminal: &mut Terminal<B>, app: &mut App) -> io::Result<()> {
-    let index = app.get_focused_note().unwrap();
+    let CurrentScreen::NoteEdit(index) = app.current_screen else {
+        return Ok(());
+    };
     let note = app.notes.get(index).unwrap();
-    let mut text_area = TextArea::new(note.get_note_text_vec());
-    text_area.set_yank_text(&app.clipboard);
-    text_area.set_block(Mode::Normal.block(&note.title));
-    text_area.set_cursor_style(Mode::Normal.cursor_style());
-    let mut vim = Vim::new(Mode::Normal);
+    let title = note.title.clone();
+    let original = note.get_note_text_vec();
+    let mut editor = NoteEditor::new(original.clone());
+    editor.text_area.set_yank_text(&app.clipboard);
+    editor.text_area.set_block(Mode::Normal.block(&title));
+    editor
+        .text_area
+        .set_cursor_style(Mode::Normal.cursor_style());
+    let mut vim = Vim::new(Mode::Normal, app.config.edit);
     loop {
+        if app.config.edit.word_count {
+            let (words, chars) = crate::utils::text_counts(editor.text_area.lines());
+            editor.text_area.set_block(
+                vim.mode.block(&title).title(
+                    Title::from(format!("{} words, {} chars", words, chars))
+                        .position(Position::Bottom)
+                        .alignment(Alignment::Right),
+                ),
+            );
+        }
         terminal.draw(|f| {
             ui(f, app);
-            f.render_widget(text_area.widget(), centered_rect(70, 70, f.size()))
+            f.render_widget(editor.text_area.widget(), centered_rect(70, 70, f.size()))
         })?;
 
-        vim = match vim.transition(crossterm::event::read()?.into(), &mut text_area) {
-            Transition::Mode(mode) if vim.mode != mode => {
-                text_area.set_block(mode.block(&note.title));
-                text_area.set_cursor_style(mode.cursor_style());
-                Vim::new(mode)
-            }
-            Transition::Nop | Transition::Mode(_) => vim,
-            Transition::Pending(input) => vim.with_pending(input),
+        let input = read_input(Duration::from_millis(app.config.edit.esc_timeout_ms))?;
+        app.toast = None;
+        let transition = editor.transition(&vim, input, &mut app.notes[index]);
+        if let Some(mode) = transition.mode().filter(|&mode| mode != vim.mode) {
+            editor.text_area.set_block(mode.block(&title));
+            editor.text_area.set_cursor_style(mode.cursor_style());
+        }
+        match &transition {
+            Transition::Warn(message) => app.toast = Some(message.clone()),
             Transition::Quit => {
-                app.clipboard = text_area.yank_text();
+                app.clipboard = editor.text_area.yank_text();
                 break;
             }
+            _ => {}
         }
+        vim = vim.next(transition, &editor.text_area);
     }
     let note = app.notes.get_mut(index).unwrap();
-    if note.items != text_area.lines().to_vec() {
+    if original != editor.text_area.lines() {
         app.modified = true;
     }
-    note.items = text_area.lines().to_vec();
+    note.items = editor.text_area.lines().to_vec();
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{Config, EditConfig};
+    use ratatui::backend::TestBackend;
+
+    fn keys(keys: &str) -> Vec<Input> {
+        keys.chars()
+            .map(|c| match c {
+                // Stand-ins for keys with modifiers
+                'J' => Input {
+                    key: Key::Char('j'),
+                    alt: true,
+                    ..Input::default()
+                },
+                'R' => Input {
+                    key: Key::Char('r'),
+                    ctrl: true,
+                    ..Input::default()
+                },
+                '\n' => Input {
+                    key: Key::Enter,
+                    ..Input::default()
+                },
+                '\x1b' => Input {
+                    key: Key::Esc,
+                    ..Input::default()
+                },
+                c => Input {
+                    key: Key::Char(c),
+                    ..Input::default()
+                },
+            })
+            .collect()
+    }
+
+    // Runs the keys through the editor the way vim_mode does and returns the resulting lines
+    fn edit(lines: &[&str], config: EditConfig, input: &str) -> Vec<String> {
+        let mut note = Note::new("Test".to_string());
+        let mut editor = NoteEditor::new(lines.iter().map(|line| line.to_string()).collect());
+        let mut vim = Vim::new(Mode::Normal, config);
+        for input in keys(input) {
+            let transition = editor.transition(&vim, input, &mut note);
+            vim = vim.next(transition, &editor.text_area);
+        }
+        editor.text_area.lines().to_vec()
+    }
+
+    #[test]
+    fn undo_right_after_a_move_restores_the_order() {
+        let lines = ["[ ] a", "[ ] b"];
+        let config = EditConfig::default();
+        assert_eq!(edit(&lines, config, "J"), ["[ ] b", "[ ] a"]);
+        assert_eq!(edit(&lines, config, "Ju"), lines);
+        assert_eq!(edit(&lines, config, "Juu"), lines);
+        assert_eq!(edit(&lines, config, "JuR"), ["[ ] b", "[ ] a"]);
+    }
+
+    #[test]
+    fn undo_continues_past_a_move_into_earlier_edits() {
+        let lines = ["[ ] a", "[ ] b"];
+        let config = EditConfig::default();
+        assert_eq!(edit(&lines, config, "x\x1bJ"), ["[ ] b", " ] a"]);
+        assert_eq!(edit(&lines, config, "xJu"), [" ] a", "[ ] b"]);
+        assert_eq!(edit(&lines, config, "xJuu"), lines);
+        assert_eq!(edit(&lines, config, "xJuuRR"), ["[ ] b", " ] a"]);
+        // Edits after a move are undone before the move itself
+        assert_eq!(edit(&lines, config, "JxuR"), ["[ ] b", " ] a"]);
+        assert_eq!(edit(&lines, config, "Jxuu"), lines);
+    }
+
+    #[test]
+    fn editing_after_undo_drops_the_redo() {
+        let lines = ["[ ] a", "[ ] b"];
+        let config = EditConfig::default();
+        assert_eq!(edit(&lines, config, "JuxR"), [" ] a", "[ ] b"]);
+    }
+
+    #[test]
+    fn undo_right_after_a_cascading_toggle_restores_the_items() {
+        let lines = ["[~] P", "\t[ ] a", "\t[~] b"];
+        let config = EditConfig {
+            hierarchical: true,
+            ..EditConfig::default()
+        };
+        assert_eq!(edit(&lines, config, "\n"), ["[x] P", "\t[x] a", "\t[x] b"]);
+        assert_eq!(edit(&lines, config, "\nu"), lines);
+        assert_eq!(
+            edit(&lines, config, "\nuR"),
+            ["[x] P", "\t[x] a", "\t[x] b"]
+        );
+    }
+
+    #[test]
+    fn tree_connectors_skip_hidden_completed_items() {
+        let mut note = Note::new("Tree".to_string());
+        note.items = vec![
+            "[ ] P".to_string(),
+            "\t[ ] a".to_string(),
+            "\t[x] b".to_string(),
+        ];
+        let mut config = Config::default();
+        config.layout.tree_items = true;
+        config.layout.hide_completed = true;
+        let app = App::new(vec![note], config);
+        let mut terminal = Terminal::new(TestBackend::new(40, 12)).unwrap();
+        terminal.draw(|f| ui(f, &app)).unwrap();
+        let buffer = terminal.backend().buffer();
+        let rows: Vec<String> = (0..12)
+            .map(|y| (0..40).map(|x| buffer.get(x, y).symbol()).collect())
+            .collect();
+        assert!(rows.iter().any(|row| row.contains("└─")), "{:#?}", rows);
+        assert!(!rows.iter().any(|row| row.contains("├─")), "{:#?}", rows);
+    }
+
+    #[test]
+    fn wrapped_height_counts_display_width() {
+        assert_eq!(wrapped_height("ab ab", 6), 1);
+        assert_eq!(wrapped_height("日本 日本", 6), 2);
+        // Wide characters are never split across lines
+        assert_eq!(wrapped_height("日本語日本語", 5), 3);
+    }
+
+    #[test]
+    fn wide_messages_fit_their_popup() {
+        let text = format!("{} - Press any key to continue", "日本語のメモ ".repeat(12));
+        let (rows, max_scroll) = render_message(60, 20, &text, 0);
+        assert_eq!(max_scroll, 0);
+        assert!(
+            rows.iter().any(|row| row.contains("continue")),
+            "{:#?}",
+            rows
+        );
+    }
+
+    fn render_message(width: u16, height: u16, text: &str, scroll: u16) -> (Vec<String>, u16) {
+        let app = App::new(Vec::new(), Config::default());
+        let mut terminal = Terminal::new(TestBackend::new(width, height)).unwrap();
+        let mut max_scroll = 0;
+        terminal
+            .draw(|f| max_scroll = draw_message(f, &app, text, Color::LightBlue, scroll))
+            .unwrap();
+        let buffer = terminal.backend().buffer();
+        let rows = (0..height)
+            .map(|y| (0..width).map(|x| buffer.get(x, y).symbol()).collect())
+            .collect();
+        (rows, max_scroll)
+    }
+
+    #[test]
+    fn help_on_a_short_terminal_uses_the_full_width() {
+        let text = format!("{} - Press any key to continue", crate::HELP);
+        let (rows, max_scroll) = render_message(40, 12, &text, 0);
+        assert_eq!(max_scroll, 0);
+        assert!(rows
+            .iter()
+            .any(|row| row.starts_with('┌') && row.ends_with('┐')));
+        assert!(rows.iter().any(|row| row.contains("continue")));
+    }
+
+    #[test]
+    fn help_that_does_not_fit_can_be_scrolled_to_the_end() {
+        let text = format!("{} - Press any key to continue", crate::HELP);
+        let (rows, max_scroll) = render_message(30, 8, &text, 0);
+        assert!(max_scroll > 0);
+        assert!(!rows.iter().any(|row| row.contains("continue")));
+        assert!(rows.iter().any(|row| row.contains("j/k to scroll")));
+
+        let (rows, _) = render_message(30, 8, &text, max_scroll);
+        assert!(rows.iter().any(|row| row.contains("continue")));
+    }
+
+    #[test]
+    fn help_fits_without_scrolling_on_a_large_terminal() {
+        let text = format!("{} - Press any key to continue", crate::HELP);
+        let (rows, max_scroll) = render_message(120, 40, &text, 0);
+        assert_eq!(max_scroll, 0);
+        assert!(rows.iter().any(|row| row.contains("continue")));
+    }
+}
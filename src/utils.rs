@@ -1,62 +1,351 @@
-use crate::note::Note;
-use std::fs::File;
+use crate::note::{ItemStatus, Note};
+use std::ffi::OsString;
+use std::fs::{File, OpenOptions};
 use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
 
-pub fn complete_item(mut line: String) -> String {
-    if line.contains("[ ]") {
-        line = line.replace("[ ]", "[x]");
-    } else {
-        line = line.replace("[x]", "[ ]");
+pub fn complete_item(line: String) -> String {
+    match ItemStatus::split(&line).1 {
+        Some(status) => set_item_status(line, status.next()),
+        None => line,
     }
-    line
 }
 
-pub fn get_notes_from_file() -> Option<Vec<Note>> {
-    let mut home_path = std::env::var_os("HOME").unwrap_or("/home/sam".into());
-    home_path.push("/.config/keep/keep_config.txt");
-    let path = std::path::Path::new(&home_path);
-    if let Ok(file) = File::open(path) {
-        let reader = io::BufReader::new(file).lines();
-        let mut vec = Vec::new();
-        for line in reader.flatten() {
-            vec.push(note_from_line(line));
+pub fn set_item_status(line: String, new_status: ItemStatus) -> String {
+    match ItemStatus::split(&line) {
+        (indent, Some(status), _) => {
+            let rest = &line[indent.len() + status.marker().len()..];
+            format!("{}{}{}", indent, new_status.marker(), rest)
         }
-        Some(vec)
-    } else {
-        None
+        _ => line,
     }
 }
 
-pub fn note_from_line(line: String) -> Note {
-    let mut parts = line.split(';');
-    let title = parts.next().unwrap();
-    let mut note = Note::new(title.to_string());
+pub fn item_text(line: &str) -> &str {
+    ItemStatus::split(line).2
+}
+
+// Nesting depth of an item, counting a tab or four spaces as one level
+pub fn indent_level(line: &str) -> usize {
+    let width: usize = ItemStatus::split(line)
+        .0
+        .chars()
+        .map(|c| if c == '\t' { 4 } else { 1 })
+        .sum();
+    width / 4
+}
+
+pub fn text_counts(lines: &[String]) -> (usize, usize) {
+    lines
+        .iter()
+        .map(|line| item_text(line))
+        .fold((0, 0), |(words, chars), text| {
+            (
+                words + text.split_whitespace().count(),
+                chars + text.chars().count(),
+            )
+        })
+}
+
+pub fn truncate_to(s: &str, width: usize) -> String {
+    if s.width() <= width {
+        return s.to_string();
+    }
+    if width == 0 {
+        return String::new();
+    }
+    let mut truncated = String::new();
+    let mut used = 0;
+    for c in s.chars() {
+        let char_width = c.width().unwrap_or(0);
+        // Leave room for the ellipsis
+        if used + char_width + 1 > width {
+            break;
+        }
+        used += char_width;
+        truncated.push(c);
+    }
+    truncated.push('…');
+    truncated
+}
+
+// Directories searched for keep's files, in order: $XDG_CONFIG_HOME/keep, $HOME/.config/keep
+pub fn config_dirs() -> Vec<PathBuf> {
+    config_dirs_from(
+        std::env::var_os("XDG_CONFIG_HOME"),
+        std::env::var_os("HOME"),
+    )
+}
+
+fn config_dirs_from(xdg_config_home: Option<OsString>, home: Option<OsString>) -> Vec<PathBuf> {
+    let mut dirs = Vec::new();
+    if let Some(xdg_path) = xdg_config_home.filter(|path| !path.is_empty()) {
+        dirs.push(Path::new(&xdg_path).join("keep"));
+    }
+    let home_path = home.unwrap_or("/home/sam".into());
+    dirs.push(Path::new(&home_path).join(".config/keep"));
+    dirs
+}
+
+// The first existing file with this name in the config directories, or the preferred location
+pub fn config_file(name: &str) -> PathBuf {
+    config_file_in(&config_dirs(), name)
+}
+
+fn config_file_in(dirs: &[PathBuf], name: &str) -> PathBuf {
+    dirs.iter()
+        .map(|dir| dir.join(name))
+        .find(|path| path.exists())
+        .unwrap_or_else(|| dirs[0].join(name))
+}
+
+pub fn notes_path() -> PathBuf {
+    config_file("keep_config.txt")
+}
 
-    for part in parts {
-        note.items.push(part.to_string())
+// Opens the file for appending without changing it, to find out whether saving can succeed
+pub fn is_writable(path: &Path) -> bool {
+    OpenOptions::new().append(true).open(path).is_ok()
+}
+
+// None if there is no notes file. A line that can't be read is an error rather than the end of
+// the notes, so the notes after it are not lost on the next save
+pub fn get_notes_from_file() -> io::Result<Option<Vec<Note>>> {
+    match File::open(notes_path()) {
+        Ok(file) => read_notes(file).map(Some),
+        Err(_) => Ok(None),
     }
+}
+
+pub fn read_notes(reader: impl io::Read) -> io::Result<Vec<Note>> {
+    io::BufReader::new(reader)
+        .lines()
+        .map(|line| line.map(note_from_line))
+        .collect()
+}
 
+// The title and each item are terminated by a ';'. A ';' or '\' inside them is escaped with '\'
+pub fn note_from_line(line: String) -> Note {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some(escaped @ ('\\' | ';')) => field.push(escaped),
+                Some(other) => {
+                    field.push('\\');
+                    field.push(other);
+                }
+                None => field.push('\\'),
+            },
+            ';' => fields.push(std::mem::take(&mut field)),
+            c => field.push(c),
+        }
+    }
+    // Tolerate a missing terminator on the last part
+    if !field.is_empty() {
+        fields.push(field);
+    }
+
+    let mut fields = fields.into_iter();
+    let mut note = Note::new(fields.next().unwrap_or_default());
+    note.items.extend(fields);
     note
 }
 
-pub fn write_notes_to_file(notes: &Vec<Note>) -> io::Result<()> {
-    let mut home_path = std::env::var_os("HOME").unwrap_or("/home/sam".into());
-    home_path.push("/.config/keep/keep_config.txt");
-    let mut file = File::create(home_path).unwrap();
+pub fn note_to_line(note: &Note) -> String {
+    let mut line = String::new();
+    for field in std::iter::once(&note.title).chain(&note.items) {
+        line.push_str(&field.replace('\\', "\\\\").replace(';', "\\;"));
+        line.push(';');
+    }
+    line
+}
 
+pub fn write_notes_to_file(notes: &[Note], path: &Path) -> io::Result<()> {
+    let mut file = File::create(path)?;
     for note in notes {
-        let size = note.items.iter().fold(0, |acc, e| acc + e.len());
-        let mut content = String::with_capacity(size + note.title.len());
-        content.push_str(&(note.title.clone() + ";"));
+        file.write_all((note_to_line(note) + "\n").as_bytes())?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // An empty directory for this test under the system temp dir
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("keep-test-{}-{}", std::process::id(), name));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn xdg_config_home_is_searched_first() {
+        let dirs = config_dirs_from(Some("/xdg".into()), Some("/home/me".into()));
+        assert_eq!(
+            dirs,
+            [
+                PathBuf::from("/xdg/keep"),
+                PathBuf::from("/home/me/.config/keep")
+            ]
+        );
+    }
+
+    #[test]
+    fn unset_or_empty_xdg_config_home_is_skipped() {
+        let expected = [PathBuf::from("/home/me/.config/keep")];
+        assert_eq!(config_dirs_from(None, Some("/home/me".into())), expected);
+        assert_eq!(
+            config_dirs_from(Some("".into()), Some("/home/me".into())),
+            expected
+        );
+    }
+
+    #[test]
+    fn config_file_falls_back_to_an_existing_home_file() {
+        let root = scratch_dir("config-file");
+        let (xdg, home) = (root.join("xdg"), root.join("home"));
+        let dirs = config_dirs_from(Some(xdg.clone().into()), Some(home.clone().into()));
 
-        for item in &note.items {
-            content.push_str(&item);
-            content.push_str(";");
+        // Nothing exists yet, so new files go to the XDG location
+        assert_eq!(
+            config_file_in(&dirs, "config.toml"),
+            xdg.join("keep/config.toml")
+        );
+
+        std::fs::create_dir_all(home.join(".config/keep")).unwrap();
+        std::fs::write(home.join(".config/keep/config.toml"), "").unwrap();
+        assert_eq!(
+            config_file_in(&dirs, "config.toml"),
+            home.join(".config/keep/config.toml")
+        );
+
+        std::fs::create_dir_all(xdg.join("keep")).unwrap();
+        std::fs::write(xdg.join("keep/config.toml"), "").unwrap();
+        assert_eq!(
+            config_file_in(&dirs, "config.toml"),
+            xdg.join("keep/config.toml")
+        );
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn writing_to_a_missing_directory_is_an_error() {
+        let root = scratch_dir("write-missing-dir");
+        let path = root.join("missing/keep_config.txt");
+        let notes = [Note::new("Title".to_string())];
+        assert!(write_notes_to_file(&notes, &path).is_err());
+        assert!(!path.exists());
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn written_notes_read_back() {
+        let root = scratch_dir("write-read");
+        let path = root.join("keep_config.txt");
+        let mut note = Note::new("Title".to_string());
+        note.items = vec!["[ ] one".to_string(), "[x] two; three".to_string()];
+        write_notes_to_file(&[note], &path).unwrap();
+        let notes = read_notes(File::open(&path).unwrap()).unwrap();
+        assert_eq!(notes.len(), 1);
+        assert_eq!(notes[0].items, ["[ ] one", "[x] two; three"]);
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    // Root can write to read-only files, so permission bits only limit other users
+    fn running_as_root() -> bool {
+        std::fs::read_to_string("/proc/self/status").is_ok_and(|status| {
+            status
+                .lines()
+                .any(|line| line.starts_with("Uid:") && line.split_whitespace().nth(2) == Some("0"))
+        })
+    }
+
+    #[test]
+    fn read_only_file_is_not_writable() {
+        let root = scratch_dir("read-only");
+        let path = root.join("keep_config.txt");
+        std::fs::write(&path, "A;[ ] one;\n").unwrap();
+        assert!(is_writable(&path));
+
+        let mut permissions = std::fs::metadata(&path).unwrap().permissions();
+        permissions.set_readonly(true);
+        std::fs::set_permissions(&path, permissions.clone()).unwrap();
+        if !running_as_root() {
+            assert!(!is_writable(&path));
         }
+        // Checking must not have changed the file
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "A;[ ] one;\n");
 
-        content.push('\n');
+        #[allow(clippy::permissions_set_readonly_false)]
+        permissions.set_readonly(false);
+        std::fs::set_permissions(&path, permissions).unwrap();
+        std::fs::remove_dir_all(root).unwrap();
+    }
 
-        file.write_all(content.as_bytes())?;
+    #[test]
+    fn missing_file_is_not_writable() {
+        let root = scratch_dir("missing-file");
+        assert!(!is_writable(&root.join("keep_config.txt")));
+        assert!(!root.join("keep_config.txt").exists());
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    fn round_trip(title: &str, items: &[&str]) {
+        let mut note = Note::new(title.to_string());
+        note.items = items.iter().map(|item| item.to_string()).collect();
+        let read = note_from_line(note_to_line(&note));
+        assert_eq!(read.title, note.title);
+        assert_eq!(read.items, note.items);
+    }
+
+    #[test]
+    fn notes_round_trip() {
+        round_trip("Groceries", &["[ ] milk", "[x] eggs", "\t[~] bread"]);
+    }
+
+    #[test]
+    fn empty_items_and_notes_round_trip() {
+        round_trip("Empty", &[]);
+        round_trip("", &[]);
+        round_trip("Gaps", &["", "[ ] one", "", ""]);
+    }
+
+    #[test]
+    fn separators_in_text_round_trip() {
+        round_trip(
+            "a; b",
+            &["[ ] buy milk; eggs", ";", "back\\slash", "ends with \\"],
+        );
+        assert_eq!(
+            note_to_line(&note_from_line("T;[ ] a\\; b;".to_string())),
+            "T;[ ] a\\; b;"
+        );
+    }
+
+    #[test]
+    fn reads_lines_written_before_escaping() {
+        let note = note_from_line("Paths;[ ] C:\\temp;[ ] no terminator".to_string());
+        assert_eq!(note.title, "Paths");
+        assert_eq!(note.items, ["[ ] C:\\temp", "[ ] no terminator"]);
+    }
+
+    #[test]
+    fn unreadable_line_is_an_error() {
+        let data: &[u8] = b"A;[ ] one;\nB;[ ] \xff;\nC;[ ] three;\n";
+        assert!(read_notes(data).is_err());
+    }
+
+    #[test]
+    fn reads_every_line() {
+        let data: &[u8] = b"A;[ ] one;\nB;\nC;[x] three;\n";
+        let notes = read_notes(data).unwrap();
+        let titles: Vec<&str> = notes.iter().map(|note| note.title.as_str()).collect();
+        assert_eq!(titles, ["A", "B", "C"]);
     }
-    Ok(())
 }
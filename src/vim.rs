@@ -1,3 +1,5 @@
+use crate::config::EditConfig;
+use crate::note::ItemStatus;
 use ratatui::layout::Alignment;
 use ratatui::style::{Color, Modifier, Style};
 use ratatui::widgets::block::{Position, Title};
@@ -16,13 +18,15 @@ pub enum Mode {
 impl Mode {
     pub fn block<'a>(&self, note_title: &str) -> Block<'a> {
         let help = match self {
-            Self::Normal => "[q]uit, [i]nsert mode, [n]ew item",
+            Self::Normal => {
+                "[q]uit, [i]nsert mode, [gi] resume insert, [n]ew item, [Alt-j/k] move item"
+            }
             Self::Insert => "<ESC> for normal mode",
             Self::Visual => "[y]ank, [d]elete",
             Self::Operator(_) => "move cursor to apply operator",
         };
         let mode = format!("{} MODE ({})", self, help);
-        let note_title = format!("{}", note_title);
+        let note_title = note_title.to_string();
         Block::default()
             .style(Style::default().fg(Color::Gray))
             .borders(Borders::ALL)
@@ -61,30 +65,111 @@ pub enum Transition {
     Nop,
     Mode(Mode),
     Pending(Input),
+    Warn(String),
+    MoveItemDown,
+    MoveItemUp,
+    ToggleItem,
+    Undo,
+    Redo,
     Quit,
 }
 
+impl Transition {
+    // The mode the editor ends up in, for transitions that set one
+    pub fn mode(&self) -> Option<Mode> {
+        match self {
+            Self::Mode(mode) => Some(*mode),
+            Self::Undo | Self::Redo => Some(Mode::Normal),
+            _ => None,
+        }
+    }
+}
+
 // State of Vim emulation
 pub struct Vim {
     pub mode: Mode,
     pub pending: Input, // Pending input to handle a sequence with two keys like gg
+    pub last_insert: Option<(usize, usize)>, // Cursor position when Insert mode was last left
+    pub config: EditConfig,
 }
 
 impl Vim {
-    pub fn new(mode: Mode) -> Self {
+    pub fn new(mode: Mode, config: EditConfig) -> Self {
         Self {
             mode,
             pending: Input::default(),
+            last_insert: None,
+            config,
         }
     }
 
     pub fn with_pending(self, pending: Input) -> Self {
+        Self { pending, ..self }
+    }
+
+    pub fn with_mode(self, mode: Mode, textarea: &TextArea<'_>) -> Self {
+        let last_insert = if self.mode == Mode::Insert {
+            Some(textarea.cursor())
+        } else {
+            self.last_insert
+        };
         Self {
-            mode: self.mode,
-            pending,
+            last_insert,
+            ..Self::new(mode, self.config)
+        }
+    }
+
+    // The state after a transition. Anything but another pending key completes the sequence, so
+    // the pending key is cleared and can't combine with a later key
+    pub fn next(self, transition: Transition, textarea: &TextArea<'_>) -> Self {
+        match (transition.mode(), transition) {
+            (Some(mode), _) if self.mode != mode => self.with_mode(mode, textarea),
+            (_, Transition::Pending(input)) => self.with_pending(input),
+            _ => self.with_pending(Input::default()),
+        }
+    }
+
+    fn exceeds_item_limit(&self, textarea: &TextArea<'_>, added: usize) -> bool {
+        self.config.max_items != 0
+            && added > 0
+            && textarea.lines().len() + added > self.config.max_items
+    }
+
+    // Lines the default insert mode mapping of the input would add
+    fn lines_added(input: &Input, textarea: &TextArea<'_>) -> usize {
+        match input {
+            Input {
+                key: Key::Char('m'),
+                ctrl: true,
+                alt: false,
+                ..
+            }
+            | Input {
+                key: Key::Char('\n' | '\r'),
+                ctrl: false,
+                alt: false,
+                ..
+            } => 1,
+            Input {
+                key: Key::Char('y'),
+                ctrl: true,
+                alt: false,
+                ..
+            }
+            | Input {
+                key: Key::Paste, ..
+            } => textarea.yank_text().matches('\n').count(),
+            _ => 0,
         }
     }
 
+    fn item_limit_warning(&self) -> Transition {
+        Transition::Warn(format!(
+            "Notes are limited to {} items",
+            self.config.max_items
+        ))
+    }
+
     pub fn transition(&self, input: Input, textarea: &mut TextArea<'_>) -> Transition {
         if input.key == Key::Null {
             return Transition::Nop;
@@ -107,10 +192,16 @@ impl Vim {
                         alt: true,
                         ..
                     } => {
+                        // Remove one level of indentation: a tab or up to four spaces
+                        let (row, _) = textarea.cursor();
+                        let indent = ItemStatus::split(&textarea.lines()[row]).0;
+                        let width = if indent.starts_with('\t') {
+                            1
+                        } else {
+                            indent.chars().take_while(|&c| c == ' ').take(4).count()
+                        };
                         textarea.move_cursor(CursorMove::Head);
-                        textarea.delete_next_word();
-                        textarea.set_yank_text("[");
-                        textarea.paste();
+                        textarea.delete_str(width);
                         textarea.move_cursor(CursorMove::WordForward);
                         textarea.move_cursor(CursorMove::WordForward);
                     }
@@ -120,6 +211,9 @@ impl Vim {
                         textarea.move_cursor(CursorMove::WordForward);
                         textarea.move_cursor(CursorMove::WordForward);
                     }
+                    Input {
+                        key: Key::Enter, ..
+                    } if self.config.hierarchical => return Transition::ToggleItem,
                     Input {
                         key: Key::Enter, ..
                     } => {
@@ -133,6 +227,16 @@ impl Vim {
                         key: Key::Char('h'),
                         ..
                     } => textarea.move_cursor(CursorMove::Back),
+                    Input {
+                        key: Key::Char('j'),
+                        alt: true,
+                        ..
+                    } if self.mode == Mode::Normal => return Transition::MoveItemDown,
+                    Input {
+                        key: Key::Char('k'),
+                        alt: true,
+                        ..
+                    } if self.mode == Mode::Normal => return Transition::MoveItemUp,
                     Input {
                         key: Key::Char('j'),
                         ..
@@ -181,6 +285,16 @@ impl Vim {
                         textarea.cancel_selection();
                         return Transition::Mode(Mode::Insert);
                     }
+                    Input {
+                        key: Key::Char('p'),
+                        ..
+                    } if self.exceeds_item_limit(
+                        textarea,
+                        textarea.yank_text().matches('\n').count(),
+                    ) =>
+                    {
+                        return self.item_limit_warning()
+                    }
                     Input {
                         key: Key::Char('p'),
                         ..
@@ -192,18 +306,12 @@ impl Vim {
                         key: Key::Char('u'),
                         ctrl: false,
                         ..
-                    } => {
-                        textarea.undo();
-                        return Transition::Mode(Mode::Normal);
-                    }
+                    } => return Transition::Undo,
                     Input {
                         key: Key::Char('r'),
                         ctrl: true,
                         ..
-                    } => {
-                        textarea.redo();
-                        return Transition::Mode(Mode::Normal);
-                    }
+                    } => return Transition::Redo,
                     Input {
                         key: Key::Char('x'),
                         ..
@@ -211,6 +319,25 @@ impl Vim {
                         textarea.delete_next_char();
                         return Transition::Mode(Mode::Normal);
                     }
+                    Input {
+                        key: Key::Char('i'),
+                        ctrl: false,
+                        ..
+                    } if matches!(
+                        self.pending,
+                        Input {
+                            key: Key::Char('g'),
+                            ctrl: false,
+                            ..
+                        }
+                    ) =>
+                    {
+                        textarea.cancel_selection();
+                        if let Some((row, col)) = self.last_insert {
+                            textarea.move_cursor(CursorMove::Jump(row as u16, col as u16));
+                        }
+                        return Transition::Mode(Mode::Insert);
+                    }
                     Input {
                         key: Key::Char('i'),
                         ..
@@ -234,6 +361,10 @@ impl Vim {
                         textarea.move_cursor(CursorMove::End);
                         return Transition::Mode(Mode::Insert);
                     }
+                    Input {
+                        key: Key::Char('o' | 'O'),
+                        ..
+                    } if self.exceeds_item_limit(textarea, 1) => return self.item_limit_warning(),
                     Input {
                         key: Key::Char('o'),
                         ..
@@ -356,6 +487,26 @@ impl Vim {
                             textarea.move_cursor(CursorMove::End); // At the last line, move to end of the line instead
                         }
                     }
+                    Input {
+                        key: Key::Char('y'),
+                        ctrl: false,
+                        ..
+                    } if self.mode == Mode::Normal
+                        && matches!(
+                            self.pending,
+                            Input {
+                                key: Key::Char('g'),
+                                ctrl: false,
+                                ..
+                            }
+                        ) =>
+                    {
+                        // Yank the item text without its indentation and checkbox
+                        let (row, _) = textarea.cursor();
+                        let text = crate::utils::item_text(&textarea.lines()[row]).to_string();
+                        textarea.set_yank_text(text);
+                        return Transition::Mode(Mode::Normal);
+                    }
                     Input {
                         key: Key::Char(op @ ('y' | 'd' | 'c')),
                         ctrl: false,
@@ -415,6 +566,12 @@ impl Vim {
                     ctrl: true,
                     ..
                 } => Transition::Mode(Mode::Normal),
+                Input {
+                    key: Key::Enter, ..
+                } if self.exceeds_item_limit(textarea, 1) => self.item_limit_warning(),
+                input if self.exceeds_item_limit(textarea, Self::lines_added(&input, textarea)) => {
+                    self.item_limit_warning()
+                }
                 Input {
                     key: Key::Enter, ..
                 } => {
@@ -422,7 +579,7 @@ impl Vim {
                     textarea.insert_newline();
                     textarea.set_yank_text("[ ] ");
                     textarea.paste();
-                    return Transition::Mode(Mode::Insert);
+                    Transition::Mode(Mode::Insert)
                 }
                 input => {
                     textarea.input(input); // Use default key mappings in insert mode
@@ -432,3 +589,126 @@ impl Vim {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(c: char) -> Input {
+        let key = match c {
+            '\x1b' => Key::Esc,
+            c => Key::Char(c),
+        };
+        Input {
+            key,
+            ..Input::default()
+        }
+    }
+
+    fn feed(vim: Vim, textarea: &mut TextArea<'_>, keys: &str) -> Vim {
+        keys.chars().fold(vim, |vim, c| {
+            let transition = vim.transition(key(c), textarea);
+            vim.next(transition, textarea)
+        })
+    }
+
+    fn textarea(lines: &[&str]) -> TextArea<'static> {
+        TextArea::new(lines.iter().map(|line| line.to_string()).collect())
+    }
+
+    #[test]
+    fn alt_tab_unindents_one_level_for_every_marker() {
+        let indents = [
+            ("\t", ""),
+            ("    ", ""),
+            ("\t\t", "\t"),
+            ("        ", "    "),
+            ("", ""),
+        ];
+        for marker in ["[ ]", "[~]", "[x]"] {
+            for (before, after) in indents {
+                let mut textarea = textarea(&[&format!("{}{} foo", before, marker)]);
+                let vim = Vim::new(Mode::Normal, EditConfig::default());
+                let alt_tab = Input {
+                    key: Key::Tab,
+                    alt: true,
+                    ..Input::default()
+                };
+                vim.transition(alt_tab, &mut textarea);
+                assert_eq!(textarea.lines(), [format!("{}{} foo", after, marker)]);
+            }
+        }
+    }
+
+    #[test]
+    fn gy_yanks_the_item_text_only() {
+        let mut textarea = textarea(&["\t[~] one", "[ ] two"]);
+        let vim = Vim::new(Mode::Normal, EditConfig::default());
+        feed(vim, &mut textarea, "gy");
+        assert_eq!(textarea.yank_text(), "one");
+    }
+
+    #[test]
+    fn yy_after_gy_yanks_the_whole_line() {
+        let mut textarea = textarea(&["[ ] one", "[ ] two"]);
+        let vim = Vim::new(Mode::Normal, EditConfig::default());
+        feed(vim, &mut textarea, "gyjyy");
+        assert_eq!(textarea.yank_text(), "[ ] two");
+    }
+
+    #[test]
+    fn pasting_lines_respects_the_item_limit() {
+        for (max_items, len) in [(2, 2), (3, 3), (0, 3)] {
+            let mut textarea = textarea(&["[ ] one", "[ ] two"]);
+            let config = EditConfig {
+                max_items,
+                ..EditConfig::default()
+            };
+            feed(Vim::new(Mode::Normal, config), &mut textarea, "yjp");
+            assert_eq!(textarea.lines().len(), len, "max_items = {}", max_items);
+        }
+    }
+
+    #[test]
+    fn ctrl_m_respects_the_item_limit() {
+        for (max_items, len) in [(2, 2), (3, 3)] {
+            let mut textarea = textarea(&["[ ] one", "[ ] two"]);
+            let config = EditConfig {
+                max_items,
+                ..EditConfig::default()
+            };
+            let vim = feed(Vim::new(Mode::Normal, config), &mut textarea, "i");
+            let ctrl_m = Input {
+                key: Key::Char('m'),
+                ctrl: true,
+                ..Input::default()
+            };
+            let transition = vim.transition(ctrl_m, &mut textarea);
+            assert_eq!(
+                matches!(transition, Transition::Warn(_)),
+                len == 2,
+                "max_items = {}",
+                max_items
+            );
+            assert_eq!(textarea.lines().len(), len, "max_items = {}", max_items);
+        }
+    }
+
+    #[test]
+    fn gg_then_i_inserts_at_the_cursor() {
+        let mut textarea = textarea(&["[ ] one", "[ ] two", "[ ] three"]);
+        let vim = Vim::new(Mode::Normal, EditConfig::default());
+        let vim = feed(vim, &mut textarea, "GA\x1bggi");
+        assert_eq!(vim.mode, Mode::Insert);
+        assert_eq!(textarea.cursor().0, 0);
+    }
+
+    #[test]
+    fn gi_resumes_at_the_last_insert_position() {
+        let mut textarea = textarea(&["[ ] one", "[ ] two", "[ ] three"]);
+        let vim = Vim::new(Mode::Normal, EditConfig::default());
+        let vim = feed(vim, &mut textarea, "GA\x1bgggi");
+        assert_eq!(vim.mode, Mode::Insert);
+        assert_eq!(textarea.cursor(), (2, 9));
+    }
+}